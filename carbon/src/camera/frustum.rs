@@ -0,0 +1,230 @@
+use crate::scene::{Aabb, BoundingSphere};
+use math::{
+    mat::Mat4,
+    vec::{Vec3, Vec4},
+};
+
+// Points with a non-negative signed distance are on the inner side of the plane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    pub fn from_normal_and_point(normal: Vec3, point: Vec3) -> Self {
+        let normal = normal.normalized();
+        Self {
+            normal,
+            distance: -normal.dot(&point),
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(&point) + self.distance
+    }
+}
+
+// Convex view volume bounded by inward facing planes. Perspective cameras use an
+// infinite far plane, so their frustum only has five planes.
+#[derive(Debug, Clone, Default)]
+pub struct Frustum {
+    planes: Vec<Plane>,
+}
+
+impl Frustum {
+    pub fn from_planes(planes: Vec<Plane>) -> Self {
+        Self { planes }
+    }
+
+    // Gribb/Hartmann plane extraction, for clip volumes with 0 <= z <= w. Planes with a
+    // vanishing normal, like the far plane of an infinite projection, are left out
+    pub fn from_matrix(proj_view: Mat4) -> Self {
+        let columns = [
+            proj_view * Vec4::new(1.0, 0.0, 0.0, 0.0),
+            proj_view * Vec4::new(0.0, 1.0, 0.0, 0.0),
+            proj_view * Vec4::new(0.0, 0.0, 1.0, 0.0),
+            proj_view * Vec4::new(0.0, 0.0, 0.0, 1.0),
+        ];
+        let [x, y, z, w] = [Vec4::x, Vec4::y, Vec4::z, Vec4::w].map(|row| {
+            Vec4::new(
+                row(&columns[0]),
+                row(&columns[1]),
+                row(&columns[2]),
+                row(&columns[3]),
+            )
+        });
+        let planes = [w + x, w - x, w + y, w - y, z, w - z]
+            .into_iter()
+            .filter_map(|coefficients| {
+                let normal = Vec3::new(coefficients.x(), coefficients.y(), coefficients.z());
+                let length = normal.dot(&normal).sqrt();
+                (length > f32::EPSILON).then(|| Plane {
+                    normal: normal * (1.0 / length),
+                    distance: coefficients.w() / length,
+                })
+            })
+            .collect();
+        Self::from_planes(planes)
+    }
+
+    pub fn planes(&self) -> &[Plane] {
+        &self.planes
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative: boxes near the frustum's corners can pass without being visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Vec3::new(
+                if plane.normal.x() >= 0.0 {
+                    aabb.max.x()
+                } else {
+                    aabb.min.x()
+                },
+                if plane.normal.y() >= 0.0 {
+                    aabb.max.y()
+                } else {
+                    aabb.min.y()
+                },
+                if plane.normal.z() >= 0.0 {
+                    aabb.max.z()
+                } else {
+                    aabb.min.z()
+                },
+            );
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{look_to, new_infinite_perspective_proj, new_orthographic_proj};
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Vec3::new(x, y, z),
+            radius,
+        }
+    }
+
+    fn cube(x: f32, y: f32, z: f32, half_size: f32) -> Aabb {
+        Aabb {
+            min: Vec3::new(x - half_size, y - half_size, z - half_size),
+            max: Vec3::new(x + half_size, y + half_size, z + half_size),
+        }
+    }
+
+    // 90 degree field of view looking down -Z from the origin, so |x| <= -z is visible
+    fn perspective() -> Frustum {
+        let view = look_to(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let proj = new_infinite_perspective_proj(1.0, std::f32::consts::FRAC_PI_2, 0.1);
+        Frustum::from_matrix(proj * view)
+    }
+
+    // -2 <= x <= 2, -1 <= y <= 1 and -10 <= z <= -0.5
+    fn orthographic() -> Frustum {
+        let view = look_to(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let proj = new_orthographic_proj(-2.0, 2.0, -1.0, 1.0, 0.5, 10.0);
+        Frustum::from_matrix(proj * view)
+    }
+
+    #[test]
+    fn perspective_planes() {
+        let frustum = perspective();
+        assert_eq!(frustum.planes().len(), 5);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1.0)));
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1e6)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.05)));
+        assert!(!frustum.contains_point(Vec3::new(1.1, 0.0, -1.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, -1.1, -1.0)));
+    }
+
+    #[test]
+    fn perspective_spheres() {
+        let frustum = perspective();
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, 10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(20.0, 0.0, -10.0, 1.0)));
+        // Crossing the left plane and the near plane
+        assert!(frustum.intersects_sphere(&sphere(10.5, 0.0, -10.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 0.0, 0.2)));
+    }
+
+    #[test]
+    fn perspective_boxes() {
+        let frustum = perspective();
+        assert!(frustum.intersects_aabb(&cube(0.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(0.0, 0.0, 10.0, 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(0.0, -20.0, -10.0, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(0.0, -10.5, -10.0, 1.0)));
+        assert!(!frustum.intersects_aabb(&Aabb::default()));
+    }
+
+    #[test]
+    fn orthographic_spheres() {
+        let frustum = orthographic();
+        assert_eq!(frustum.planes().len(), 6);
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, -5.0, 0.5)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, -20.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(3.5, 0.0, -5.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(2.5, 0.0, -5.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, -10.5, 1.0)));
+    }
+
+    #[test]
+    fn orthographic_boxes() {
+        let frustum = orthographic();
+        assert!(frustum.intersects_aabb(&cube(0.0, 0.0, -5.0, 0.5)));
+        assert!(!frustum.intersects_aabb(&cube(0.0, 0.0, 1.0, 0.25)));
+        assert!(!frustum.intersects_aabb(&cube(0.0, 2.5, -5.0, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(0.0, 1.5, -5.0, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(0.0, 0.0, -10.5, 1.0)));
+    }
+
+    #[test]
+    fn looking_straight_down() {
+        let view = look_to(
+            Vec3::new(0.0, 10.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let proj = new_infinite_perspective_proj(1.0, std::f32::consts::FRAC_PI_2, 0.1);
+        let frustum = Frustum::from_matrix(proj * view);
+        assert!(frustum.planes().iter().all(|plane| {
+            plane.distance.is_finite()
+                && plane.normal.x().is_finite()
+                && plane.normal.y().is_finite()
+                && plane.normal.z().is_finite()
+        }));
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 0.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 20.0, 0.0, 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(15.0, 0.0, 0.0, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(10.5, 0.0, 0.0, 1.0)));
+    }
+}
@@ -1,5 +1,12 @@
+mod frustum;
+
+pub use frustum::{Frustum, Plane};
+
 use crate::input::MouseState;
-use math::{mat::Mat4, vec::Vec3};
+use math::{
+    mat::Mat4,
+    vec::{Vec3, Vec4},
+};
 
 static ROTATION_DELTA: f32 = 10.0;
 static MOVEMENT_DELTA: f32 = 0.005;
@@ -15,13 +22,13 @@ fn new_orthographic_proj(
     far: f32,
 ) -> Mat4 {
     let w_inv = 1.0 / (right - left);
-    let h_inv = 1.0 / (bottom - top);
+    let h_inv = 1.0 / (top - bottom);
     let d_inv = 1.0 / (far - near);
     Mat4::from_data(
-        2.0 * w_inv, 0.0, 0.0, 0.0,
-        0.0, 2.0 * h_inv, 0.0, -(bottom + top) * h_inv,
-        0.0, 0.0, d_inv, 0.0,
-        -(left + right) * w_inv, -(top + bottom) * h_inv, d_inv * near, 1.0,
+        2.0 * w_inv, 0.0,         0.0,    -(right + left) * w_inv,
+        0.0,         2.0 * h_inv, 0.0,    -(top + bottom) * h_inv,
+        0.0,         0.0,         -d_inv, -near * d_inv,
+        0.0,         0.0,         0.0,    1.0,
     )
 }
 
@@ -36,10 +43,11 @@ fn new_infinite_perspective_proj(aspect_ratio: f32, y_fov: f32, z_near: f32) ->
     )
 }
 
+// `up` only has to be linearly independent from `front`, it is made orthogonal here
 #[rustfmt::skip]
-fn look_to(eye: Vec3, front: Vec3, world_up: Vec3) -> Mat4 {
+fn look_to(eye: Vec3, front: Vec3, up: Vec3) -> Mat4 {
     let front = (front * -1.0).normalized();
-    let side = world_up.cross(&front).normalized();
+    let side = up.cross(&front).normalized();
     let up = front.cross(&side);
 
     Mat4::from_data(
@@ -50,28 +58,57 @@ fn look_to(eye: Vec3, front: Vec3, world_up: Vec3) -> Mat4 {
     )
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrthographicFit {
+    // Use the magnifications as given, ignoring the window's aspect ratio
+    Stretch,
+    // Keep the horizontal extent and derive the vertical one from the aspect ratio
+    Width,
+    // Keep the vertical extent and derive the horizontal one from the aspect ratio
+    Height,
+    // Grow one of the extents so that the whole authored area stays visible
+    Both,
+}
+
+// Magnifications are half-extents of the view volume, centred on the view axis
 #[derive(Debug)]
 pub struct OrtographicData {
-    pub left: f32,
-    pub right: f32,
-    pub top: f32,
-    pub bottom: f32,
+    pub x_mag: f32,
+    pub y_mag: f32,
     pub near: f32,
     pub far: f32,
+    pub fit: OrthographicFit,
+}
+
+impl OrtographicData {
+    pub fn extents(&self, aspect_ratio: f32) -> (f32, f32) {
+        match self.fit {
+            OrthographicFit::Stretch => (self.x_mag, self.y_mag),
+            OrthographicFit::Width => (self.x_mag, self.x_mag / aspect_ratio),
+            OrthographicFit::Height => (self.y_mag * aspect_ratio, self.y_mag),
+            OrthographicFit::Both => {
+                if aspect_ratio > self.x_mag / self.y_mag {
+                    (self.y_mag * aspect_ratio, self.y_mag)
+                } else {
+                    (self.x_mag, self.x_mag / aspect_ratio)
+                }
+            }
+        }
+    }
 }
 
 impl Default for OrtographicData {
     fn default() -> Self {
         Self {
-            left: 0.0,
-            right: 1.0,
-            top: 0.0,
-            bottom: 1.0,
+            x_mag: 1.0,
+            y_mag: 1.0,
             near: 0.0,
             far: 100.0,
+            fit: OrthographicFit::Both,
         }
     }
 }
+
 #[derive(Debug)]
 pub struct PerspectiveData {
     pub aspect_ratio: Option<f32>,
@@ -108,7 +145,9 @@ impl CameraType {
 
     pub fn projection(&self, window_width: f32, window_height: f32) -> CameraProjection {
         match self {
-            Self::Orthographic(data) => CameraProjection::new_orthographic(data),
+            Self::Orthographic(data) => {
+                CameraProjection::new_orthographic(data, window_width, window_height)
+            }
             Self::Perspective(data) => {
                 CameraProjection::new_perspective(data, window_width, window_height)
             }
@@ -123,14 +162,10 @@ pub enum CameraProjection {
 }
 
 impl CameraProjection {
-    pub fn new_orthographic(data: &OrtographicData) -> Self {
+    pub fn new_orthographic(data: &OrtographicData, window_width: f32, window_height: f32) -> Self {
+        let (x_extent, y_extent) = data.extents(window_width / window_height);
         Self::Orthographic(new_orthographic_proj(
-            data.left,
-            data.right,
-            data.bottom,
-            data.top,
-            data.near,
-            data.far,
+            -x_extent, x_extent, -y_extent, y_extent, data.near, data.far,
         ))
     }
 
@@ -172,21 +207,25 @@ pub enum UpdateSpeed {
 
 #[derive(Debug)]
 pub struct Camera {
-    pos: Vec3,
-    front: Vec3,
-    ty: CameraType,
-    rotation_speed: f32,
-    movement_speed: f32,
+    pub(crate) name: Option<String>,
+    pub(crate) pos: Vec3,
+    pub(crate) front: Vec3,
+    pub(crate) up: Vec3,
+    pub(crate) ty: CameraType,
+    pub(crate) rotation_speed: f32,
+    pub(crate) movement_speed: f32,
     // TODO: Stop using yaw and pitch later
-    yaw: f32,
-    pitch: f32,
+    pub(crate) yaw: f32,
+    pub(crate) pitch: f32,
 }
 
 impl Camera {
     pub fn from_type(ty: CameraType) -> Self {
         Self {
+            name: None,
             pos: Default::default(),
             front: Vec3::new(1.0, 0.0, 0.0),
+            up: WORLD_UP,
             ty,
             rotation_speed: 100.0,
             movement_speed: 0.01,
@@ -195,6 +234,25 @@ impl Camera {
         }
     }
 
+    pub fn from_type_and_transform(ty: CameraType, transform: Mat4) -> Self {
+        let mut camera = Self::from_type(ty);
+        camera.set_transform(transform);
+        camera
+    }
+
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn ty(&self) -> &CameraType {
+        &self.ty
+    }
+
     pub fn pos(&self) -> &Vec3 {
         &self.pos
     }
@@ -203,6 +261,28 @@ impl Camera {
         &self.front
     }
 
+    pub fn up(&self) -> &Vec3 {
+        &self.up
+    }
+
+    // Cameras look down their local -Z axis, with +Y up. Keeping the up axis preserves any
+    // roll, and lets cameras look straight up or down.
+    pub fn set_transform(&mut self, transform: Mat4) {
+        let pos = transform * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let front = transform * Vec4::new(0.0, 0.0, -1.0, 0.0);
+        let up = transform * Vec4::new(0.0, 1.0, 0.0, 0.0);
+        self.pos = Vec3::new(pos.x(), pos.y(), pos.z());
+        self.up = Vec3::new(up.x(), up.y(), up.z()).normalized();
+        self.set_front(Vec3::new(front.x(), front.y(), front.z()));
+    }
+
+    fn set_front(&mut self, front: Vec3) {
+        self.front = front.normalized();
+        // Keep yaw and pitch in sync so mouse rotation continues from the new orientation
+        self.pitch = self.front.y().clamp(-1.0, 1.0).asin().to_degrees();
+        self.yaw = self.front.z().atan2(self.front.x()).to_degrees();
+    }
+
     pub fn update_rotation_speed(&mut self, update: UpdateSpeed) {
         match update {
             UpdateSpeed::Decrease => self.rotation_speed -= ROTATION_DELTA,
@@ -219,7 +299,12 @@ impl Camera {
     }
 
     pub fn update_position(&mut self, direction: Direction) {
-        let flat_front = Vec3::new(self.front.x(), 0.0, self.front.z());
+        // Cameras looking straight up or down move towards the top of the screen instead
+        let flat_front = if self.front.x().abs() + self.front.z().abs() > f32::EPSILON {
+            Vec3::new(self.front.x(), 0.0, self.front.z())
+        } else {
+            Vec3::new(self.up.x(), 0.0, self.up.z())
+        };
         let left = WORLD_UP.cross(&flat_front).normalized();
         match direction {
             Direction::Front => self.pos += flat_front * self.movement_speed,
@@ -244,6 +329,8 @@ impl Camera {
                 pitch_r.sin(),
                 yaw_r.sin() * pitch_r.cos(),
             );
+            // Mouse look has no roll, and pitch is clamped so the world up is always usable
+            self.up = WORLD_UP;
         }
     }
 
@@ -252,7 +339,7 @@ impl Camera {
         let eye = self.pos;
         let front = self.front;
 
-        let view = look_to(eye, front, WORLD_UP);
+        let view = look_to(eye, front, self.up);
         let proj = self
             .ty
             .projection(window_width, window_height)
@@ -261,4 +348,162 @@ impl Camera {
         let proj_view = proj * view;
         CameraMatrices { proj_view }
     }
+
+    // World space, taken from the matrix used to render so that culling matches it exactly
+    pub fn frustum(&self, window_width: f32, window_height: f32) -> Frustum {
+        Frustum::from_matrix(self.get_matrices(window_width, window_height).proj_view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Transform;
+
+    fn ortho_camera(rotation: Vec4, translation: Vec3) -> Camera {
+        let transform = Transform {
+            translation,
+            rotation,
+            ..Default::default()
+        };
+        Camera::from_type_and_transform(
+            CameraType::Orthographic(OrtographicData::default()),
+            transform.matrix(),
+        )
+    }
+
+    fn clip_position(camera: &Camera, point: Vec3) -> Vec4 {
+        camera.get_matrices(100.0, 100.0).proj_view
+            * Vec4::new(point.x(), point.y(), point.z(), 1.0)
+    }
+
+    fn assert_mat_eq(actual: Mat4, expected: Mat4) {
+        for basis in [
+            Vec4::new(1.0, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 1.0, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 1.0, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        ] {
+            let (actual, expected) = (actual * basis, expected * basis);
+            let difference = actual - expected;
+            assert!(
+                difference.dot(&difference) < 1e-12,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    // glTF half-extents of 2 by 1, on windows wider and narrower than that
+    fn orthographic_matrix(fit: OrthographicFit, window_width: f32) -> Mat4 {
+        let data = OrtographicData {
+            x_mag: 2.0,
+            y_mag: 1.0,
+            near: 0.0,
+            far: 10.0,
+            fit,
+        };
+        CameraProjection::new_orthographic(&data, window_width, 100.0).to_raw_matrix()
+    }
+
+    #[rustfmt::skip]
+    fn reference_orthographic(x_extent: f32, y_extent: f32) -> Mat4 {
+        Mat4::from_data(
+            1.0 / x_extent, 0.0,            0.0,   0.0,
+            0.0,            1.0 / y_extent, 0.0,   0.0,
+            0.0,            0.0,            -0.1,  0.0,
+            0.0,            0.0,            0.0,   1.0,
+        )
+    }
+
+    #[test]
+    fn orthographic_stretch() {
+        for window_width in [400.0, 100.0] {
+            assert_mat_eq(
+                orthographic_matrix(OrthographicFit::Stretch, window_width),
+                reference_orthographic(2.0, 1.0),
+            );
+        }
+    }
+
+    #[test]
+    fn orthographic_fit_width() {
+        assert_mat_eq(
+            orthographic_matrix(OrthographicFit::Width, 400.0),
+            reference_orthographic(2.0, 0.5),
+        );
+        assert_mat_eq(
+            orthographic_matrix(OrthographicFit::Width, 100.0),
+            reference_orthographic(2.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn orthographic_fit_height() {
+        assert_mat_eq(
+            orthographic_matrix(OrthographicFit::Height, 400.0),
+            reference_orthographic(4.0, 1.0),
+        );
+        assert_mat_eq(
+            orthographic_matrix(OrthographicFit::Height, 100.0),
+            reference_orthographic(1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn orthographic_fit_both() {
+        // Wider windows grow the horizontal extent, narrower ones the vertical one
+        assert_mat_eq(
+            orthographic_matrix(OrthographicFit::Both, 400.0),
+            reference_orthographic(4.0, 1.0),
+        );
+        assert_mat_eq(
+            orthographic_matrix(OrthographicFit::Both, 100.0),
+            reference_orthographic(2.0, 2.0),
+        );
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn orthographic_off_centre_depth() {
+        assert_mat_eq(
+            new_orthographic_proj(0.0, 4.0, -1.0, 3.0, 1.0, 5.0),
+            Mat4::from_data(
+                0.5, 0.0, 0.0,   -1.0,
+                0.0, 0.5, 0.0,   -0.5,
+                0.0, 0.0, -0.25, -0.25,
+                0.0, 0.0, 0.0,   1.0,
+            ),
+        );
+    }
+
+    #[test]
+    fn camera_looking_straight_down() {
+        // -90 degrees around X, so the camera's -Z points down and its +Y points to -Z
+        let half_angle = -std::f32::consts::FRAC_PI_4;
+        let camera = ortho_camera(
+            Vec4::new(half_angle.sin(), 0.0, 0.0, half_angle.cos()),
+            Vec3::new(0.0, 10.0, 0.0),
+        );
+        assert!((camera.up().z() + 1.0).abs() < 1e-5);
+
+        let clip = clip_position(&camera, Vec3::new(0.0, 0.0, -0.5));
+        assert!(clip.x().is_finite() && clip.y().is_finite() && clip.z().is_finite());
+        assert!(clip.x().abs() < 1e-5);
+        assert!((clip.y() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn camera_keeps_roll() {
+        // 90 degrees around Z, the camera's up is now world -X
+        let half_angle = std::f32::consts::FRAC_PI_4;
+        let camera = ortho_camera(
+            Vec4::new(0.0, 0.0, half_angle.sin(), half_angle.cos()),
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        let clip = clip_position(&camera, Vec3::new(-0.5, 0.0, -5.0));
+        assert!(clip.x().abs() < 1e-5);
+        assert!((clip.y() - 0.5).abs() < 1e-5);
+    }
 }
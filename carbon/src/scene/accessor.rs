@@ -0,0 +1,188 @@
+use gltf::accessor::{sparse::IndexType, DataType, Dimensions};
+
+// Accessors are decoded here rather than with gltf's reader, because it doesn't handle the
+// component types KHR_mesh_quantization allows or sparse accessors without a buffer view.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    I8,
+    U8,
+    I16,
+    U16,
+    U32,
+    F32,
+}
+
+impl From<DataType> for ComponentType {
+    fn from(data_type: DataType) -> Self {
+        match data_type {
+            DataType::I8 => Self::I8,
+            DataType::U8 => Self::U8,
+            DataType::I16 => Self::I16,
+            DataType::U16 => Self::U16,
+            DataType::U32 => Self::U32,
+            DataType::F32 => Self::F32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessorFormat {
+    pub component_type: ComponentType,
+    pub normalized: bool,
+    pub sparse: bool,
+}
+
+impl AccessorFormat {
+    pub(super) fn new(accessor: &gltf::Accessor) -> Self {
+        Self {
+            component_type: accessor.data_type().into(),
+            normalized: accessor.normalized(),
+            sparse: accessor.sparse().is_some(),
+        }
+    }
+}
+
+// Normalized integers map to [0, 1] or [-1, 1], other integers keep their value
+fn float_component(accessor: &gltf::Accessor) -> impl Fn(&[u8]) -> f32 {
+    let data_type = accessor.data_type();
+    let max = match data_type {
+        DataType::I8 => i8::MAX as f64,
+        DataType::U8 => u8::MAX as f64,
+        DataType::I16 => i16::MAX as f64,
+        DataType::U16 => u16::MAX as f64,
+        DataType::U32 | DataType::F32 => 1.0,
+    };
+    let normalized = accessor.normalized();
+    move |bytes| {
+        let value = component_value(data_type, bytes);
+        if normalized {
+            (value / max).max(-1.0) as f32
+        } else {
+            value as f32
+        }
+    }
+}
+
+// Elements are padded to four components with zeros, matrix accessors give `None`
+pub(super) fn read_floats(
+    accessor: &gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<[f32; 4]>> {
+    read_elements(accessor, buffers, float_component(accessor))
+}
+
+// Column-major MAT4 elements, as used by inverse bind matrices
+pub(super) fn read_matrices(
+    accessor: &gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<[[f32; 4]; 4]>> {
+    if accessor.dimensions() != Dimensions::Mat4 {
+        return None;
+    }
+    let elements = read_elements::<f32, 16>(accessor, buffers, float_component(accessor))?;
+    Some(
+        elements
+            .into_iter()
+            .map(|element| {
+                [0, 1, 2, 3].map(|column| element[column * 4..][..4].try_into().unwrap())
+            })
+            .collect(),
+    )
+}
+
+// For indices and joints, which are always unsigned integers
+pub(super) fn read_integers(
+    accessor: &gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<[u32; 4]>> {
+    let data_type = accessor.data_type();
+    read_elements(accessor, buffers, |bytes| {
+        component_value(data_type, bytes) as u32
+    })
+}
+
+fn component_value(data_type: DataType, bytes: &[u8]) -> f64 {
+    match data_type {
+        DataType::I8 => bytes[0] as i8 as f64,
+        DataType::U8 => bytes[0] as f64,
+        DataType::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        DataType::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        DataType::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        DataType::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+    }
+}
+
+fn view_data<'a>(view: &gltf::buffer::View, buffers: &'a [gltf::buffer::Data]) -> Option<&'a [u8]> {
+    buffers
+        .get(view.buffer().index())?
+        .get(view.offset()..view.offset() + view.length())
+}
+
+// Elements hold up to `N` components, padded with `T::default()`. `None` for elements with
+// more components, MAT2 and MAT3 accessors, whose columns may be padded, and accessors that
+// read past the end of their buffer view.
+fn read_elements<T: Copy + Default, const N: usize>(
+    accessor: &gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+    read_component: impl Fn(&[u8]) -> T,
+) -> Option<Vec<[T; N]>> {
+    let num_components = match accessor.dimensions() {
+        Dimensions::Scalar => 1,
+        Dimensions::Vec2 => 2,
+        Dimensions::Vec3 => 3,
+        Dimensions::Vec4 => 4,
+        Dimensions::Mat4 => 16,
+        Dimensions::Mat2 | Dimensions::Mat3 => return None,
+    };
+    if num_components > N {
+        return None;
+    }
+    let component_size = accessor.data_type().size();
+    let element_size = num_components * component_size;
+    let read_element = |bytes: &[u8]| {
+        let mut element = [T::default(); N];
+        for (idx, component) in element.iter_mut().take(num_components).enumerate() {
+            *component = read_component(&bytes[idx * component_size..]);
+        }
+        element
+    };
+
+    // Accessors without a buffer view start out zeroed
+    let mut elements = match accessor.view() {
+        Some(view) => {
+            let stride = view.stride().unwrap_or(element_size);
+            let data = view_data(&view, buffers)?.get(accessor.offset()..)?;
+            (0..accessor.count())
+                .map(|idx| {
+                    data.get(idx * stride..idx * stride + element_size)
+                        .map(read_element)
+                })
+                .collect::<Option<Vec<_>>>()?
+        }
+        None => vec![[T::default(); N]; accessor.count()],
+    };
+
+    // Sparse values are tightly packed, and replace the elements at their indices
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_type = indices.index_type();
+        let index_size = index_type.size();
+        let index_data = view_data(&indices.view(), buffers)?.get(indices.offset() as usize..)?;
+        let values = sparse.values();
+        let value_data = view_data(&values.view(), buffers)?.get(values.offset() as usize..)?;
+        for idx in 0..sparse.count() as usize {
+            let index = index_data.get(idx * index_size..(idx + 1) * index_size)?;
+            let index = match index_type {
+                IndexType::U8 => index[0] as usize,
+                IndexType::U16 => u16::from_le_bytes([index[0], index[1]]) as usize,
+                IndexType::U32 => {
+                    u32::from_le_bytes([index[0], index[1], index[2], index[3]]) as usize
+                }
+            };
+            let value = value_data.get(idx * element_size..(idx + 1) * element_size)?;
+            *elements.get_mut(index)? = read_element(value);
+        }
+    }
+    Some(elements)
+}
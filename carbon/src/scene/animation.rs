@@ -0,0 +1,452 @@
+use super::{
+    accessor::read_floats,
+    compiled_scene::CompiledScene,
+    transform::{slerp, Transform},
+};
+use math::vec::{Vec3, Vec4};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+impl From<gltf::animation::Interpolation> for Interpolation {
+    fn from(interpolation: gltf::animation::Interpolation) -> Self {
+        match interpolation {
+            gltf::animation::Interpolation::Step => Self::Step,
+            gltf::animation::Interpolation::Linear => Self::Linear,
+            gltf::animation::Interpolation::CubicSpline => Self::CubicSpline,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    MorphTargetWeights,
+}
+
+impl From<gltf::animation::Property> for Property {
+    fn from(property: gltf::animation::Property) -> Self {
+        match property {
+            gltf::animation::Property::Translation => Self::Translation,
+            gltf::animation::Property::Rotation => Self::Rotation,
+            gltf::animation::Property::Scale => Self::Scale,
+            gltf::animation::Property::MorphTargetWeights => Self::MorphTargetWeights,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    pub inputs: Vec<f32>,
+    // Flattened keyframe values, stored as (in-tangent, value, out-tangent) for cubic splines
+    pub outputs: Vec<f32>,
+}
+
+impl Channel {
+    fn new(channel: &gltf::animation::Channel, buffers: &[gltf::buffer::Data]) -> Option<Self> {
+        let sampler = channel.sampler();
+        let property = channel.target().property().into();
+        let inputs = read_floats(&sampler.input(), buffers)?
+            .into_iter()
+            .map(|[time, _, _, _]| time)
+            .collect::<Vec<f32>>();
+        let num_components = match property {
+            Property::Translation | Property::Scale => 3,
+            Property::Rotation => 4,
+            Property::MorphTargetWeights => 1,
+        };
+        let outputs = read_floats(&sampler.output(), buffers)?
+            .into_iter()
+            .flat_map(|value| value.into_iter().take(num_components))
+            .collect::<Vec<f32>>();
+        Some(Self {
+            node: channel.target().node().index(),
+            property,
+            interpolation: sampler.interpolation().into(),
+            inputs,
+            outputs,
+        })
+    }
+
+    pub fn num_components(&self) -> usize {
+        match self.property {
+            Property::Translation | Property::Scale => 3,
+            Property::Rotation => 4,
+            Property::MorphTargetWeights => {
+                let values_per_key = match self.interpolation {
+                    Interpolation::CubicSpline => 3,
+                    _ => 1,
+                };
+                self.outputs.len() / (self.inputs.len() * values_per_key).max(1)
+            }
+        }
+    }
+
+    fn key(&self, key: usize, offset: usize) -> &[f32] {
+        let n = self.num_components();
+        let key = match self.interpolation {
+            Interpolation::CubicSpline => key * 3 + offset,
+            _ => key,
+        };
+        &self.outputs[key * n..(key + 1) * n]
+    }
+
+    // Writes the channel's value at `time` into `out`, which must hold `num_components` values
+    pub fn sample(&self, time: f32, out: &mut [f32]) {
+        let (first, last) = match (self.inputs.first(), self.inputs.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return,
+        };
+        // Past the end every interpolation holds the last key, step channels would
+        // otherwise stay on the one before it
+        if time >= last {
+            out.copy_from_slice(self.key(self.inputs.len() - 1, 1));
+            return;
+        }
+        let time = time.max(first);
+        let next = self
+            .inputs
+            .partition_point(|input| *input <= time)
+            .min(self.inputs.len() - 1);
+        let prev = next.saturating_sub(1);
+
+        let delta = self.inputs[next] - self.inputs[prev];
+        if prev == next || delta <= 0.0 {
+            out.copy_from_slice(self.key(prev, 1));
+            return;
+        }
+        let t = (time - self.inputs[prev]) / delta;
+
+        match self.interpolation {
+            Interpolation::Step => out.copy_from_slice(self.key(prev, 1)),
+            Interpolation::Linear => {
+                let (a, b) = (self.key(prev, 0), self.key(next, 0));
+                if self.property == Property::Rotation {
+                    let q = slerp(
+                        Vec4::new(a[0], a[1], a[2], a[3]),
+                        Vec4::new(b[0], b[1], b[2], b[3]),
+                        t,
+                    );
+                    out.copy_from_slice(&[q.x(), q.y(), q.z(), q.w()]);
+                } else {
+                    for (i, value) in out.iter_mut().enumerate() {
+                        *value = a[i] + (b[i] - a[i]) * t;
+                    }
+                }
+            }
+            Interpolation::CubicSpline => {
+                let (p0, m0) = (self.key(prev, 1), self.key(prev, 2));
+                let (p1, m1) = (self.key(next, 1), self.key(next, 0));
+                let t2 = t * t;
+                let t3 = t2 * t;
+                for (i, value) in out.iter_mut().enumerate() {
+                    *value = (2.0 * t3 - 3.0 * t2 + 1.0) * p0[i]
+                        + (t3 - 2.0 * t2 + t) * delta * m0[i]
+                        + (-2.0 * t3 + 3.0 * t2) * p1[i]
+                        + (t3 - t2) * delta * m1[i];
+                }
+                if self.property == Property::Rotation {
+                    let q = Vec4::new(out[0], out[1], out[2], out[3]).normalized();
+                    out.copy_from_slice(&[q.x(), q.y(), q.z(), q.w()]);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Animation {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    pub duration: f32,
+}
+
+impl Animation {
+    pub fn new(animation: &gltf::Animation, buffers: &[gltf::buffer::Data]) -> Self {
+        let channels = animation
+            .channels()
+            .filter_map(|channel| Channel::new(&channel, buffers))
+            .collect::<Vec<_>>();
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.inputs.last())
+            .fold(0.0, |duration: f32, time| duration.max(*time));
+        Self {
+            name: animation.name().map(str::to_owned),
+            channels,
+            duration,
+        }
+    }
+
+    pub fn wrap_time(&self, time: f32, looping: bool) -> f32 {
+        if looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        }
+    }
+}
+
+pub struct AnimationPlayer {
+    rest_pose: Vec<Transform>,
+    pose: Vec<Transform>,
+    morph_weights: Vec<Vec<f32>>,
+}
+
+impl AnimationPlayer {
+    pub fn new(scene: &CompiledScene) -> Self {
+        let rest_pose = scene
+            .graph
+            .nodes()
+            .iter()
+            .map(|node| node.transform)
+            .collect::<Vec<_>>();
+        Self {
+            pose: rest_pose.clone(),
+            morph_weights: vec![Vec::new(); rest_pose.len()],
+            rest_pose,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pose.copy_from_slice(&self.rest_pose);
+        for weights in &mut self.morph_weights {
+            weights.clear();
+        }
+    }
+
+    pub fn pose(&self) -> &[Transform] {
+        &self.pose
+    }
+
+    // Animated morph target weights of every node, empty for nodes without weight channels
+    pub fn morph_weights(&self) -> &[Vec<f32>] {
+        &self.morph_weights
+    }
+
+    // Blends the animation's pose at `time` over the current pose, a weight of 1 replaces it
+    pub fn apply(&mut self, animation: &Animation, time: f32, weight: f32) {
+        let mut values = Vec::new();
+        for channel in &animation.channels {
+            values.resize(channel.num_components(), 0.0);
+            channel.sample(time, &mut values);
+
+            let transform = &mut self.pose[channel.node];
+            match channel.property {
+                Property::Translation => {
+                    let target = Vec3::new(values[0], values[1], values[2]);
+                    transform.translation =
+                        transform.translation + (target - transform.translation) * weight;
+                }
+                Property::Rotation => {
+                    let target = Vec4::new(values[0], values[1], values[2], values[3]);
+                    transform.rotation = slerp(transform.rotation, target, weight);
+                }
+                Property::Scale => {
+                    let target = Vec3::new(values[0], values[1], values[2]);
+                    transform.scale = transform.scale + (target - transform.scale) * weight;
+                }
+                Property::MorphTargetWeights => {
+                    let weights = &mut self.morph_weights[channel.node];
+                    weights.resize(values.len(), 0.0);
+                    for (current, target) in weights.iter_mut().zip(&values) {
+                        *current += (target - *current) * weight;
+                    }
+                }
+            }
+        }
+    }
+
+    // Writes the pose into the scene graph and propagates it to the scene's draws
+    pub fn update_scene(&self, scene: &mut CompiledScene) {
+        for (idx, transform) in self.pose.iter().enumerate() {
+            scene.graph.set_transform(idx, *transform);
+        }
+        scene.update_transforms();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step_channel() -> Channel {
+        Channel {
+            node: 0,
+            property: Property::Translation,
+            interpolation: Interpolation::Step,
+            inputs: vec![0.0, 1.0, 2.0],
+            outputs: vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0],
+        }
+    }
+
+    fn sample(channel: &Channel, time: f32) -> f32 {
+        let mut out = [0.0; 3];
+        channel.sample(time, &mut out);
+        out[0]
+    }
+
+    #[test]
+    fn step_holds_each_key_until_the_next() {
+        let channel = step_channel();
+        assert_eq!(sample(&channel, -1.0), 0.0);
+        assert_eq!(sample(&channel, 0.5), 0.0);
+        assert_eq!(sample(&channel, 1.0), 1.0);
+        assert_eq!(sample(&channel, 1.99), 1.0);
+    }
+
+    #[test]
+    fn step_reaches_the_last_key() {
+        let channel = step_channel();
+        assert_eq!(sample(&channel, 2.0), 2.0);
+        assert_eq!(sample(&channel, 5.0), 2.0);
+    }
+
+    #[test]
+    fn linear_translation_interpolates_between_keys() {
+        let channel = Channel {
+            interpolation: Interpolation::Linear,
+            ..step_channel()
+        };
+        assert!((sample(&channel, 0.25) - 0.25).abs() < 1e-5);
+        assert!((sample(&channel, 1.5) - 1.5).abs() < 1e-5);
+        assert_eq!(sample(&channel, 3.0), 2.0);
+    }
+
+    fn rotation_channel(to: [f32; 4]) -> Channel {
+        Channel {
+            node: 0,
+            property: Property::Rotation,
+            interpolation: Interpolation::Linear,
+            inputs: vec![0.0, 1.0],
+            outputs: vec![0.0, 0.0, 0.0, 1.0, to[0], to[1], to[2], to[3]],
+        }
+    }
+
+    #[test]
+    fn linear_rotation_slerps() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        // 90 degrees around z, halfway is 45 degrees
+        let channel = rotation_channel([0.0, 0.0, half, half]);
+        let mut out = [0.0; 4];
+        channel.sample(0.5, &mut out);
+        let angle = std::f32::consts::FRAC_PI_8;
+        assert!((out[2] - angle.sin()).abs() < 1e-5, "{:?}", out);
+        assert!((out[3] - angle.cos()).abs() < 1e-5, "{:?}", out);
+    }
+
+    #[test]
+    fn linear_rotation_takes_the_shortest_path() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        // The same 90 degree rotation with a negated quaternion
+        let channel = rotation_channel([0.0, 0.0, -half, -half]);
+        let mut out = [0.0; 4];
+        channel.sample(0.5, &mut out);
+        let angle = std::f32::consts::FRAC_PI_8;
+        assert!((out[2] - angle.sin()).abs() < 1e-5, "{:?}", out);
+        assert!((out[3] - angle.cos()).abs() < 1e-5, "{:?}", out);
+    }
+
+    fn cubic_channel(out_tangent: f32) -> Channel {
+        Channel {
+            node: 0,
+            property: Property::Translation,
+            interpolation: Interpolation::CubicSpline,
+            inputs: vec![0.0, 2.0],
+            outputs: vec![
+                // in-tangent, value, out-tangent of the first key
+                9.0,
+                9.0,
+                9.0,
+                0.0,
+                0.0,
+                0.0,
+                out_tangent,
+                out_tangent,
+                out_tangent,
+                // in-tangent, value, out-tangent of the second key
+                0.0,
+                0.0,
+                0.0,
+                2.0,
+                2.0,
+                2.0,
+                9.0,
+                9.0,
+                9.0,
+            ],
+        }
+    }
+
+    #[test]
+    fn cubic_spline_eases_with_flat_tangents() {
+        let channel = cubic_channel(0.0);
+        assert!((sample(&channel, 0.5) - 0.3125).abs() < 1e-5);
+        assert!((sample(&channel, 1.0) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cubic_spline_scales_tangents_by_the_key_interval() {
+        let channel = cubic_channel(3.0);
+        assert!((sample(&channel, 1.0) - 1.75).abs() < 1e-5);
+        // Outside the keys only values are used, never tangents
+        assert_eq!(sample(&channel, 0.0), 0.0);
+        assert_eq!(sample(&channel, 4.0), 2.0);
+    }
+
+    fn animation(channel: Channel) -> Animation {
+        let duration = *channel.inputs.last().unwrap();
+        Animation {
+            name: None,
+            channels: vec![channel],
+            duration,
+        }
+    }
+
+    fn player() -> AnimationPlayer {
+        let rest_pose = vec![Transform::default()];
+        AnimationPlayer {
+            pose: rest_pose.clone(),
+            morph_weights: vec![Vec::new()],
+            rest_pose,
+        }
+    }
+
+    #[test]
+    fn looping_wraps_time_around_the_duration() {
+        let animation = animation(step_channel());
+        assert_eq!(animation.wrap_time(2.5, true), 0.5);
+        assert_eq!(animation.wrap_time(-0.5, true), 1.5);
+        assert_eq!(animation.wrap_time(2.5, false), 2.0);
+        assert_eq!(animation.wrap_time(-0.5, false), 0.0);
+
+        let mut player = player();
+        player.apply(&animation, animation.wrap_time(3.0, true), 1.0);
+        assert_eq!(player.pose()[0].translation.x(), 1.0);
+    }
+
+    #[test]
+    fn player_blends_over_the_current_pose() {
+        let animation = animation(step_channel());
+        let mut player = player();
+        player.apply(&animation, 2.0, 0.5);
+        assert_eq!(player.pose()[0].translation.x(), 1.0);
+        player.apply(&animation, 2.0, 0.5);
+        assert_eq!(player.pose()[0].translation.x(), 1.5);
+        player.apply(&animation, 0.0, 1.0);
+        assert_eq!(player.pose()[0].translation.x(), 0.0);
+
+        player.apply(&animation, 2.0, 1.0);
+        player.reset();
+        assert_eq!(player.pose()[0], Transform::default());
+    }
+}
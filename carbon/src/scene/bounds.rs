@@ -0,0 +1,188 @@
+use math::{
+    mat::Mat4,
+    vec::{Vec3, Vec4},
+};
+
+fn min(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()))
+}
+
+fn max(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+// The empty box is inverted, so that growing it by any point gives a valid box
+impl Default for Aabb {
+    fn default() -> Self {
+        Self {
+            min: Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+}
+
+impl Aabb {
+    pub fn from_points(points: &[Vec4]) -> Self {
+        let mut aabb = Self::default();
+        for point in points {
+            aabb.grow(Vec3::new(point.x(), point.y(), point.z()));
+        }
+        aabb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x() > self.max.x() || self.min.y() > self.max.y() || self.min.z() > self.max.z()
+    }
+
+    pub fn grow(&mut self, point: Vec3) {
+        self.min = min(self.min, point);
+        self.max = max(self.max, point);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Self {
+            min: min(self.min, other.min),
+            max: max(self.max, other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3::new(a.x(), a.y(), a.z()),
+            Vec3::new(b.x(), a.y(), a.z()),
+            Vec3::new(a.x(), b.y(), a.z()),
+            Vec3::new(b.x(), b.y(), a.z()),
+            Vec3::new(a.x(), a.y(), b.z()),
+            Vec3::new(b.x(), a.y(), b.z()),
+            Vec3::new(a.x(), b.y(), b.z()),
+            Vec3::new(b.x(), b.y(), b.z()),
+        ]
+    }
+
+    // Box enclosing the transformed corners, which can be looser than the transformed geometry
+    pub fn transformed(&self, transform: Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let mut aabb = Self::default();
+        for corner in self.corners() {
+            let corner = transform * Vec4::new(corner.x(), corner.y(), corner.z(), 1.0);
+            aabb.grow(Vec3::new(corner.x(), corner.y(), corner.z()));
+        }
+        aabb
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Centered on the points' AABB, which is cheap and close enough for culling
+    pub fn from_points(points: &[Vec4]) -> Self {
+        let aabb = Aabb::from_points(points);
+        if aabb.is_empty() {
+            return Self::default();
+        }
+        let center = aabb.center();
+        let radius = points
+            .iter()
+            .map(|point| {
+                let offset = Vec3::new(point.x(), point.y(), point.z()) - center;
+                offset.dot(&offset)
+            })
+            .fold(0.0, f32::max)
+            .sqrt();
+        Self { center, radius }
+    }
+
+    // The radius is scaled by the largest axis scale, so non-uniform scales stay conservative
+    pub fn transformed(&self, transform: Mat4) -> BoundingSphere {
+        let center = transform * Vec4::new(self.center.x(), self.center.y(), self.center.z(), 1.0);
+        let scale = [
+            Vec4::new(1.0, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 1.0, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 1.0, 0.0),
+        ]
+        .into_iter()
+        .map(|axis| {
+            let axis = transform * axis;
+            axis.dot(&axis)
+        })
+        .fold(0.0, f32::max)
+        .sqrt();
+        Self {
+            center: Vec3::new(center.x(), center.y(), center.z()),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Transform;
+
+    fn assert_close(actual: Vec3, expected: [f32; 3]) {
+        let actual_array = [actual.x(), actual.y(), actual.z()];
+        for (a, b) in actual_array.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    // Scales x by 2, then rotates 90 degrees around Z and moves along x
+    fn transform() -> Mat4 {
+        let half_angle = std::f32::consts::FRAC_PI_4;
+        Transform {
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            rotation: Vec4::new(0.0, 0.0, half_angle.sin(), half_angle.cos()),
+            scale: Vec3::new(2.0, 1.0, 1.0),
+        }
+        .matrix()
+    }
+
+    #[test]
+    fn aabb_follows_rotation_and_scale() {
+        let aabb = Aabb {
+            min: Vec3::new(0.0, 0.0, 0.0),
+            max: Vec3::new(1.0, 2.0, 3.0),
+        }
+        .transformed(transform());
+        assert_close(aabb.min, [-1.0, 0.0, 0.0]);
+        assert_close(aabb.max, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn empty_aabb_stays_empty() {
+        assert!(Aabb::default().transformed(transform()).is_empty());
+        assert!(Aabb::from_points(&[]).is_empty());
+    }
+
+    #[test]
+    fn sphere_radius_grows_with_the_largest_scale() {
+        let sphere = BoundingSphere {
+            center: Vec3::new(1.0, 0.0, 0.0),
+            radius: 1.0,
+        }
+        .transformed(transform());
+        assert_close(sphere.center, [1.0, 2.0, 0.0]);
+        assert!((sphere.radius - 2.0).abs() < 1e-5);
+    }
+}
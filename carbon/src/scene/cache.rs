@@ -0,0 +1,691 @@
+use super::{
+    animation::{Animation, Channel, Interpolation, Property},
+    bounds::{Aabb, BoundingSphere},
+    compiled_scene::{
+        AlphaMode, CompiledScene, Light, LightKind, Material, MeshDraw, NormalTexture,
+        OcclusionTexture, PbrMetallicRoughness, Primitive, TextureInfo, TextureTransform, Topology,
+    },
+    gltf_scene::{Filter, ImageData, ImageFormat, SamplerData, TextureData, WrapMode},
+    graph::{SceneGraph, SceneNode},
+    morph::{MorphTarget, MorphTargets},
+    skin::Skin,
+    transform::Transform,
+};
+use crate::camera::{Camera, CameraType, OrthographicFit, OrtographicData, PerspectiveData};
+use math::{
+    mat::Mat4,
+    vec::{Vec2, Vec3, Vec4},
+};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 8] = *b"CRBNSCNE";
+// Bump whenever the layout of any cached type changes, or compiling a file gives different data
+const VERSION: u32 = 9;
+
+// Everything the renderer needs from a scene file, without keeping the document around
+pub struct CachedScene {
+    pub compiled_scene: CompiledScene,
+    pub images: Vec<ImageData>,
+    pub textures: Vec<TextureData>,
+}
+
+// Little endian binary encoding. Reading returns `None` on truncated or malformed data,
+// which is treated like a cache miss.
+pub(crate) trait Cached: Sized {
+    fn write(&self, bytes: &mut Vec<u8>);
+    fn read(reader: &mut &[u8]) -> Option<Self>;
+}
+
+macro_rules! cached_struct {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::scene::cache::Cached for $ty {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                $($crate::scene::cache::Cached::write(&self.$field, bytes);)*
+            }
+
+            fn read(reader: &mut &[u8]) -> Option<Self> {
+                Some(Self {
+                    $($field: $crate::scene::cache::Cached::read(reader)?,)*
+                })
+            }
+        }
+    };
+}
+
+macro_rules! cached_enum {
+    ($ty:ident { $($variant:ident),* $(,)? }) => {
+        impl $crate::scene::cache::Cached for $ty {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                let variants = [$($ty::$variant),*];
+                let tag = variants.iter().position(|variant| variant == self).unwrap() as u8;
+                $crate::scene::cache::Cached::write(&tag, bytes);
+            }
+
+            fn read(reader: &mut &[u8]) -> Option<Self> {
+                let variants = [$($ty::$variant),*];
+                let tag: u8 = $crate::scene::cache::Cached::read(reader)?;
+                variants.get(tag as usize).copied()
+            }
+        }
+    };
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if reader.len() < len {
+        return None;
+    }
+    let (taken, rest) = reader.split_at(len);
+    *reader = rest;
+    Some(taken)
+}
+
+macro_rules! cached_number {
+    ($($ty:ty),*) => {
+        $(impl Cached for $ty {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read(reader: &mut &[u8]) -> Option<Self> {
+                let taken = take(reader, std::mem::size_of::<$ty>())?;
+                Some(<$ty>::from_le_bytes(taken.try_into().ok()?))
+            }
+        })*
+    };
+}
+
+cached_number!(u8, u16, u32, u64, f32);
+
+impl Cached for usize {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        (*self as u64).write(bytes);
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        u64::read(reader)?.try_into().ok()
+    }
+}
+
+impl Cached for bool {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        (*self as u8).write(bytes);
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Cached> Cached for Option<T> {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.is_some().write(bytes);
+        if let Some(value) = self {
+            value.write(bytes);
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        if bool::read(reader)? {
+            Some(Some(T::read(reader)?))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+impl<T: Cached> Cached for Vec<T> {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.len().write(bytes);
+        for value in self {
+            value.write(bytes);
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        let len = usize::read(reader)?;
+        // Don't trust the length for the allocation, every element takes at least a byte
+        let mut values = Vec::with_capacity(len.min(reader.len()));
+        for _ in 0..len {
+            values.push(T::read(reader)?);
+        }
+        Some(values)
+    }
+}
+
+impl Cached for String {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.len().write(bytes);
+        bytes.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        let len = usize::read(reader)?;
+        String::from_utf8(take(reader, len)?.to_vec()).ok()
+    }
+}
+
+impl Cached for [u16; 4] {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        for value in self {
+            value.write(bytes);
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        Some([
+            u16::read(reader)?,
+            u16::read(reader)?,
+            u16::read(reader)?,
+            u16::read(reader)?,
+        ])
+    }
+}
+
+impl Cached for Vec2 {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.x().write(bytes);
+        self.y().write(bytes);
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        Some(Vec2::new(f32::read(reader)?, f32::read(reader)?))
+    }
+}
+
+impl Cached for Vec3 {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.x().write(bytes);
+        self.y().write(bytes);
+        self.z().write(bytes);
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        Some(Vec3::new(
+            f32::read(reader)?,
+            f32::read(reader)?,
+            f32::read(reader)?,
+        ))
+    }
+}
+
+impl Cached for Vec4 {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.x().write(bytes);
+        self.y().write(bytes);
+        self.z().write(bytes);
+        self.w().write(bytes);
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        Some(Vec4::new(
+            f32::read(reader)?,
+            f32::read(reader)?,
+            f32::read(reader)?,
+            f32::read(reader)?,
+        ))
+    }
+}
+
+// Stored row-major, the columns are recovered by transforming the basis vectors
+impl Cached for Mat4 {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        let columns = [
+            *self * Vec4::new(1.0, 0.0, 0.0, 0.0),
+            *self * Vec4::new(0.0, 1.0, 0.0, 0.0),
+            *self * Vec4::new(0.0, 0.0, 1.0, 0.0),
+            *self * Vec4::new(0.0, 0.0, 0.0, 1.0),
+        ];
+        for row in [Vec4::x, Vec4::y, Vec4::z, Vec4::w] {
+            for column in &columns {
+                row(column).write(bytes);
+            }
+        }
+    }
+
+    #[rustfmt::skip]
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        let mut data = [0.0; 16];
+        for value in &mut data {
+            *value = f32::read(reader)?;
+        }
+        Some(Mat4::from_data(
+            data[0],  data[1],  data[2],  data[3],
+            data[4],  data[5],  data[6],  data[7],
+            data[8],  data[9],  data[10], data[11],
+            data[12], data[13], data[14], data[15],
+        ))
+    }
+}
+
+impl Cached for LightKind {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        match self {
+            LightKind::Directional => 0u8.write(bytes),
+            LightKind::Point => 1u8.write(bytes),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                2u8.write(bytes);
+                inner_cone_angle.write(bytes);
+                outer_cone_angle.write(bytes);
+            }
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(LightKind::Directional),
+            1 => Some(LightKind::Point),
+            2 => Some(LightKind::Spot {
+                inner_cone_angle: f32::read(reader)?,
+                outer_cone_angle: f32::read(reader)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for CameraType {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        match self {
+            CameraType::Orthographic(data) => {
+                0u8.write(bytes);
+                data.write(bytes);
+            }
+            CameraType::Perspective(data) => {
+                1u8.write(bytes);
+                data.write(bytes);
+            }
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(CameraType::Orthographic(Cached::read(reader)?)),
+            1 => Some(CameraType::Perspective(Cached::read(reader)?)),
+            _ => None,
+        }
+    }
+}
+
+// World transforms aren't stored, the graph recomputes them
+impl Cached for SceneGraph {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        self.nodes().len().write(bytes);
+        for node in self.nodes() {
+            node.write(bytes);
+        }
+        self.roots().to_vec().write(bytes);
+    }
+
+    fn read(reader: &mut &[u8]) -> Option<Self> {
+        let nodes = Vec::<SceneNode>::read(reader)?;
+        let roots = Vec::<usize>::read(reader)?;
+        if roots.iter().any(|root| *root >= nodes.len()) {
+            return None;
+        }
+        Some(SceneGraph::with_roots(nodes, roots))
+    }
+}
+
+cached_enum!(AlphaMode {
+    Opaque,
+    Mask,
+    Blend
+});
+cached_enum!(Topology {
+    Points,
+    Lines,
+    Triangles
+});
+cached_enum!(Filter { Nearest, Linear });
+cached_enum!(WrapMode {
+    ClampToEdge,
+    MirroredRepeat,
+    Repeat
+});
+cached_enum!(ImageFormat {
+    Rgba8,
+    Rgba16,
+    Rgba32F
+});
+cached_enum!(Interpolation {
+    Step,
+    Linear,
+    CubicSpline
+});
+cached_enum!(Property {
+    Translation,
+    Rotation,
+    Scale,
+    MorphTargetWeights
+});
+cached_enum!(OrthographicFit {
+    Stretch,
+    Width,
+    Height,
+    Both
+});
+
+cached_struct!(TextureTransform {
+    offset,
+    rotation,
+    scale
+});
+cached_struct!(TextureInfo {
+    texture_index,
+    tex_coord,
+    transform,
+});
+cached_struct!(PbrMetallicRoughness {
+    base_color_factor,
+    base_color_texture,
+    metallic_factor,
+    roughness_factor,
+    metallic_roughness_texture,
+});
+cached_struct!(NormalTexture { texture, scale });
+cached_struct!(OcclusionTexture { texture, strength });
+cached_struct!(Material {
+    name,
+    metallic_roughness,
+    normal_texture,
+    occlusion_texture,
+    emissive_factor,
+    emissive_texture,
+    emissive_strength,
+    alpha_mode,
+    alpha_cutoff,
+    double_sided,
+    unlit,
+    transmission_factor,
+    transmission_texture,
+});
+cached_struct!(Light {
+    name,
+    kind,
+    color,
+    intensity,
+    range,
+    position,
+    direction,
+});
+cached_struct!(OrtographicData {
+    x_mag,
+    y_mag,
+    near,
+    far,
+    fit
+});
+cached_struct!(PerspectiveData {
+    aspect_ratio,
+    y_fov,
+    z_near,
+    z_far
+});
+cached_struct!(Camera {
+    name,
+    pos,
+    front,
+    up,
+    ty,
+    rotation_speed,
+    movement_speed,
+    yaw,
+    pitch,
+});
+cached_struct!(Aabb { min, max });
+cached_struct!(BoundingSphere { center, radius });
+cached_struct!(Primitive {
+    start_idx,
+    num_indices,
+    start_vertex,
+    num_vertices,
+    material_idx,
+    topology,
+    morph_targets_idx,
+    aabb,
+    bounding_sphere,
+});
+cached_struct!(MeshDraw {
+    primitive_idx,
+    material_idx,
+    topology,
+    node_idx,
+    skin_idx,
+});
+cached_struct!(Skin {
+    name,
+    joints,
+    inverse_bind_matrices,
+    skeleton,
+});
+cached_struct!(MorphTarget { positions, normals });
+cached_struct!(MorphTargets {
+    targets,
+    default_weights,
+});
+cached_struct!(Channel {
+    node,
+    property,
+    interpolation,
+    inputs,
+    outputs,
+});
+cached_struct!(Animation {
+    name,
+    channels,
+    duration,
+});
+cached_struct!(Transform {
+    translation,
+    rotation,
+    scale,
+});
+cached_struct!(SceneNode {
+    name,
+    parent,
+    children,
+    transform,
+    morph_weights,
+    mesh_draws,
+    camera,
+    light,
+});
+cached_struct!(CompiledScene {
+    positions,
+    color_sets,
+    uv_sets,
+    normals,
+    tangents,
+    joints,
+    weights,
+    indices,
+    materials,
+    primitives,
+    mesh_draws,
+    cameras,
+    lights,
+    skins,
+    morph_targets,
+    animations,
+    graph,
+});
+cached_struct!(ImageData {
+    width,
+    height,
+    format,
+    bytes,
+    mips,
+});
+cached_struct!(SamplerData {
+    mag_filter,
+    min_filter,
+    mipmap_mode,
+    wrap_s,
+    wrap_t,
+});
+cached_struct!(TextureData {
+    image_index,
+    sampler,
+});
+cached_struct!(CachedScene {
+    compiled_scene,
+    images,
+    textures,
+});
+
+// FNV-1a, stable across platforms and compiler versions unlike `DefaultHasher`
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn cache_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".cache");
+    path.with_file_name(file_name)
+}
+
+// Combined hash of the files a scene reads besides itself, `None` if one can't be read
+fn external_files_hash(external_files: &[String]) -> Option<u64> {
+    let mut hashes = Vec::new();
+    for path in external_files {
+        hash(&std::fs::read(path).ok()?).write(&mut hashes);
+    }
+    Some(hash(&hashes))
+}
+
+fn write_cache(
+    scene: &CachedScene,
+    source_hash: u64,
+    external_files: &[String],
+    external_files_hash: u64,
+) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    VERSION.write(&mut bytes);
+    source_hash.write(&mut bytes);
+    external_files.to_vec().write(&mut bytes);
+    external_files_hash.write(&mut bytes);
+    scene.write(&mut bytes);
+    bytes
+}
+
+// `None` if the cache is stale, from another version or corrupted
+fn read_cache(mut reader: &[u8], source_hash: u64) -> Option<CachedScene> {
+    if take(&mut reader, MAGIC.len())? != MAGIC
+        || u32::read(&mut reader)? != VERSION
+        || u64::read(&mut reader)? != source_hash
+    {
+        return None;
+    }
+    let external_files = Vec::<String>::read(&mut reader)?;
+    if u64::read(&mut reader)? != external_files_hash(&external_files)? {
+        return None;
+    }
+    let scene = CachedScene::read(&mut reader)?;
+    reader.is_empty().then_some(scene)
+}
+
+// Reuses `<path>.cache` when it was built from the same file contents, including the
+// external files `compile` reports, otherwise compiles the file and rewrites the cache.
+// Without a cache the next launch compiles the file again, which is no reason to fail, so
+// a failed write is returned along with the scene.
+pub(crate) fn load_or_compile(
+    path: &Path,
+    compile: impl FnOnce(&Path, &[u8]) -> anyhow::Result<(CachedScene, Vec<PathBuf>)>,
+) -> anyhow::Result<(CachedScene, Option<anyhow::Error>)> {
+    let source = std::fs::read(path)?;
+    let source_hash = hash(&source);
+    let cache_path = cache_path(path);
+    if let Ok(cache) = std::fs::read(&cache_path) {
+        if let Some(cached_scene) = read_cache(&cache, source_hash) {
+            return Ok((cached_scene, None));
+        }
+    }
+
+    let (cached_scene, external_files) = compile(path, &source)?;
+    let external_files = external_files
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let mut write_error = None;
+    if let Some(external_files_hash) = external_files_hash(&external_files) {
+        let cache = write_cache(
+            &cached_scene,
+            source_hash,
+            &external_files,
+            external_files_hash,
+        );
+        if let Err(err) = std::fs::write(&cache_path, cache) {
+            write_error = Some(anyhow::Error::new(err).context(format!(
+                "Could not write scene cache {}",
+                cache_path.display()
+            )));
+        }
+    }
+    Ok((cached_scene, write_error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{fixtures, GltfScene};
+    use serde_json::json;
+
+    // The fixture triangle with its buffer and texture in files next to `triangle.gltf`
+    fn write_triangle(dir: &Path) -> PathBuf {
+        let mut document = fixtures::textured_triangle();
+        document.push("images", json!({ "uri": "triangle.png" }));
+        document.push("textures", json!({ "source": 0 }));
+        let mut json = document.json.clone();
+        json["buffers"] = json!([{ "byteLength": document.bin.len(), "uri": "triangle.bin" }]);
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("triangle.bin"), &document.bin).unwrap();
+        std::fs::write(dir.join("triangle.png"), fixtures::png(1, 1, [255; 4])).unwrap();
+        let path = dir.join("triangle.gltf");
+        std::fs::write(&path, serde_json::to_vec(&json).unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn changed_external_files_invalidate_the_cache() {
+        let dir = std::env::temp_dir().join("carbon_cache_external_files");
+        let path = write_triangle(&dir);
+        let _ = std::fs::remove_file(cache_path(&path));
+        let (cached_scene, write_error) = GltfScene::load_cached(&path).unwrap();
+        assert_eq!(cached_scene.compiled_scene.positions[1].x(), 1.0);
+        assert!(write_error.is_none());
+        assert!(cache_path(&path).exists());
+
+        // Move the second vertex from x = 1 to x = 2
+        let mut bin = std::fs::read(dir.join("triangle.bin")).unwrap();
+        bin[12..16].copy_from_slice(&2.0f32.to_le_bytes());
+        std::fs::write(dir.join("triangle.bin"), bin).unwrap();
+        let (cached_scene, _) = GltfScene::load_cached(&path).unwrap();
+        assert_eq!(cached_scene.compiled_scene.positions[1].x(), 2.0);
+
+        let red = [255, 0, 0, 255];
+        std::fs::write(dir.join("triangle.png"), fixtures::png(1, 1, red)).unwrap();
+        let (cached_scene, _) = GltfScene::load_cached(&path).unwrap();
+        assert_eq!(cached_scene.images[0].bytes, red);
+    }
+
+    #[test]
+    fn unwritable_cache_still_loads() {
+        let dir = std::env::temp_dir().join("carbon_cache_unwritable");
+        let path = write_triangle(&dir);
+        let _ = std::fs::remove_file(cache_path(&path));
+        // A directory where the cache file should go
+        std::fs::create_dir_all(cache_path(&path)).unwrap();
+        let (cached_scene, write_error) = GltfScene::load_cached(&path).unwrap();
+        assert_eq!(cached_scene.compiled_scene.positions.len(), 3);
+        assert!(write_error.is_some());
+    }
+}
@@ -1,55 +1,754 @@
-use crate::camera::Camera;
+use super::{
+    animation::Animation,
+    bounds::{Aabb, BoundingSphere},
+    geometry::{flat_normals, generate_tangents, smooth_normals, unweld, NormalGeneration},
+    graph::SceneGraph,
+    morph::MorphTargets,
+    skin::{skin_vertex, Skin},
+};
+use crate::camera::{Camera, Frustum};
 use math::{
     mat::Mat4,
     vec::{Vec2, Vec3, Vec4},
 };
 
-#[derive(Debug)]
+// KHR_texture_transform, applied to uvs as `offset + rotation * scale * uv`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureTransform {
+    pub offset: Vec2,
+    // Radians, counter-clockwise in uv space
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl TextureTransform {
+    // `json` is the extension object, `None` if the texture doesn't use it
+    fn from_json(json: &serde_json::Value) -> Option<Self> {
+        json.as_object()?;
+        let pair = |key: &str, default: f32| {
+            let value = |idx: usize| {
+                json[key][idx]
+                    .as_f64()
+                    .map_or(default, |value| value as f32)
+            };
+            Vec2::new(value(0), value(1))
+        };
+        Some(Self {
+            offset: pair("offset", 0.0),
+            rotation: json["rotation"].as_f64().unwrap_or(0.0) as f32,
+            scale: pair("scale", 1.0),
+        })
+    }
+
+    pub fn transform_uv(&self, uv: Vec2) -> Vec2 {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (u, v) = (uv.x() * self.scale.x(), uv.y() * self.scale.y());
+        Vec2::new(
+            cos * u + sin * v + self.offset.x(),
+            -sin * u + cos * v + self.offset.y(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureInfo {
+    pub texture_index: usize,
+    // Already overridden by the texture transform's texCoord, if it has one
+    pub tex_coord: u32,
+    pub transform: Option<TextureTransform>,
+}
+
+impl TextureInfo {
+    // `json` is the texture reference's object. gltf only exposes KHR_texture_transform on some
+    // kinds of references, so it is read from the json for all of them.
+    fn new(texture_index: usize, tex_coord: u32, json: &serde_json::Value) -> Self {
+        let extension = &json["extensions"]["KHR_texture_transform"];
+        Self {
+            texture_index,
+            tex_coord: extension["texCoord"]
+                .as_u64()
+                .map_or(tex_coord, |tex_coord| tex_coord as u32),
+            transform: TextureTransform::from_json(extension),
+        }
+    }
+
+    fn from_info(info: &gltf::texture::Info, json: &serde_json::Value) -> Self {
+        Self::new(info.texture().index(), info.tex_coord(), json)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+impl From<gltf::material::AlphaMode> for AlphaMode {
+    fn from(mode: gltf::material::AlphaMode) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => Self::Opaque,
+            gltf::material::AlphaMode::Mask => Self::Mask,
+            gltf::material::AlphaMode::Blend => Self::Blend,
+        }
+    }
+}
+
+// Strips, loops and fans are converted to lists when compiling, so only lists remain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Topology {
+    Points,
+    Lines,
+    #[default]
+    Triangles,
+}
+
+#[derive(Debug, Clone)]
 pub struct PbrMetallicRoughness {
     pub base_color_factor: Vec4,
-    pub texture_index: Option<usize>,
+    pub base_color_texture: Option<TextureInfo>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureInfo>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct NormalTexture {
+    pub texture: TextureInfo,
+    pub scale: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct OcclusionTexture {
+    pub texture: TextureInfo,
+    pub strength: f32,
+}
+
+#[derive(Debug, Clone)]
 pub struct Material {
+    pub name: Option<String>,
     pub metallic_roughness: PbrMetallicRoughness,
+    pub normal_texture: Option<NormalTexture>,
+    pub occlusion_texture: Option<OcclusionTexture>,
+    pub emissive_factor: Vec3,
+    pub emissive_texture: Option<TextureInfo>,
+    // KHR_materials_emissive_strength, multiplies the emissive factor
+    pub emissive_strength: f32,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+    // KHR_materials_unlit, the base color is shown as is
+    pub unlit: bool,
+    // KHR_materials_transmission
+    pub transmission_factor: f32,
+    pub transmission_texture: Option<TextureInfo>,
 }
 
 impl Material {
-    pub fn new(mat: &gltf::material::Material) -> Self {
+    // `json` is the material's object, for the extensions gltf doesn't parse
+    pub fn new(mat: &gltf::material::Material, json: &serde_json::Value) -> Self {
         let metallic_roughness = {
             let gltf_metallic_roughness = mat.pbr_metallic_roughness();
+            let json = &json["pbrMetallicRoughness"];
 
-            let base_color_factor = gltf_metallic_roughness.base_color_factor().into();
-            let texture_index = gltf_metallic_roughness
-                .base_color_texture()
-                .map(|info| info.texture().index());
             PbrMetallicRoughness {
-                base_color_factor,
-                texture_index,
+                base_color_factor: gltf_metallic_roughness.base_color_factor().into(),
+                base_color_texture: gltf_metallic_roughness
+                    .base_color_texture()
+                    .map(|info| TextureInfo::from_info(&info, &json["baseColorTexture"])),
+                metallic_factor: gltf_metallic_roughness.metallic_factor(),
+                roughness_factor: gltf_metallic_roughness.roughness_factor(),
+                metallic_roughness_texture: gltf_metallic_roughness
+                    .metallic_roughness_texture()
+                    .map(|info| TextureInfo::from_info(&info, &json["metallicRoughnessTexture"])),
             }
         };
-        Self { metallic_roughness }
+        let normal_texture = mat.normal_texture().map(|normal| NormalTexture {
+            texture: TextureInfo::new(
+                normal.texture().index(),
+                normal.tex_coord(),
+                &json["normalTexture"],
+            ),
+            scale: normal.scale(),
+        });
+        let occlusion_texture = mat.occlusion_texture().map(|occlusion| OcclusionTexture {
+            texture: TextureInfo::new(
+                occlusion.texture().index(),
+                occlusion.tex_coord(),
+                &json["occlusionTexture"],
+            ),
+            strength: occlusion.strength(),
+        });
+        let transmission = mat.transmission();
+        Self {
+            name: mat.name().map(str::to_owned),
+            metallic_roughness,
+            normal_texture,
+            occlusion_texture,
+            emissive_factor: mat.emissive_factor().into(),
+            emissive_texture: mat
+                .emissive_texture()
+                .map(|info| TextureInfo::from_info(&info, &json["emissiveTexture"])),
+            // gltf doesn't know about KHR_materials_emissive_strength yet
+            emissive_strength: json["extensions"]["KHR_materials_emissive_strength"]
+                ["emissiveStrength"]
+                .as_f64()
+                .map_or(1.0, |strength| strength as f32),
+            alpha_mode: mat.alpha_mode().into(),
+            // The gltf spec defines 0.5 as the default cutoff for masked materials
+            alpha_cutoff: mat.alpha_cutoff().unwrap_or(0.5),
+            double_sided: mat.double_sided(),
+            unlit: mat.unlit(),
+            transmission_factor: transmission
+                .as_ref()
+                .map_or(0.0, |transmission| transmission.transmission_factor()),
+            transmission_texture: transmission
+                .and_then(|transmission| transmission.transmission_texture())
+                .map(|info| {
+                    TextureInfo::from_info(
+                        &info,
+                        &json["extensions"]["KHR_materials_transmission"]["transmissionTexture"],
+                    )
+                }),
+        }
     }
 }
 
+// Matches the material gltf uses for primitives that don't reference one
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            metallic_roughness: PbrMetallicRoughness {
+                base_color_factor: Vec4::new(1.0, 1.0, 1.0, 1.0),
+                base_color_texture: None,
+                metallic_factor: 1.0,
+                roughness_factor: 1.0,
+                metallic_roughness_texture: None,
+            },
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_factor: Vec3::new(0.0, 0.0, 0.0),
+            emissive_texture: None,
+            emissive_strength: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            unlit: false,
+            transmission_factor: 0.0,
+            transmission_texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+#[derive(Debug)]
+pub struct Light {
+    pub name: Option<String>,
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: Option<f32>,
+    // World space, lights point down their local -Z axis
+    pub position: Vec3,
+    pub direction: Vec3,
+}
+
+impl Light {
+    pub fn new(light: &gltf::khr_lights_punctual::Light, transform: Mat4) -> Self {
+        let kind = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+            gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+            gltf::khr_lights_punctual::Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        };
+        let mut light = Self {
+            name: light.name().map(str::to_owned),
+            kind,
+            color: light.color().into(),
+            intensity: light.intensity(),
+            range: light.range(),
+            position: Default::default(),
+            direction: Default::default(),
+        };
+        light.set_transform(transform);
+        light
+    }
+
+    // Lights shine down their local -Z axis
+    pub fn set_transform(&mut self, transform: Mat4) {
+        let position = transform * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let direction = transform * Vec4::new(0.0, 0.0, -1.0, 0.0);
+        self.position = Vec3::new(position.x(), position.y(), position.z());
+        self.direction = Vec3::new(direction.x(), direction.y(), direction.z()).normalized();
+    }
+}
+
+// Geometry of a single gltf primitive, shared by every draw that instances it
 #[derive(Debug, Default)]
-pub struct MeshDraw {
+pub struct Primitive {
     pub start_idx: u32,
     pub num_indices: u32,
+    pub start_vertex: u32,
+    pub num_vertices: u32,
     pub material_idx: u32,
-    // TODO: This is inneficient here, duplication it too much
-    pub transform_matrix: Mat4,
+    pub topology: Topology,
+    pub morph_targets_idx: Option<u32>,
+    // Bounds of the undeformed vertices, in the primitive's local space
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+// An instance of a primitive, placed by the world transform of its node
+#[derive(Debug, Default)]
+pub struct MeshDraw {
+    pub primitive_idx: u32,
+    pub material_idx: u32,
+    pub topology: Topology,
+    pub node_idx: u32,
+    pub skin_idx: Option<u32>,
+}
+
+// Appends a primitive's vertex sets. Sets only the scene or only the primitive has are padded
+// with `default`, so every set keeps one entry per vertex.
+fn append_vertex_sets<T: Copy>(
+    sets: &mut Vec<Vec<T>>,
+    new_sets: Vec<Vec<T>>,
+    num_vertices: usize,
+    num_new_vertices: usize,
+    default: T,
+) {
+    if sets.len() < new_sets.len() {
+        sets.resize(new_sets.len(), vec![default; num_vertices]);
+    }
+    let mut new_sets = new_sets.into_iter();
+    for set in sets.iter_mut() {
+        match new_sets.next() {
+            Some(mut new_set) => set.append(&mut new_set),
+            None => set.resize(num_vertices + num_new_vertices, default),
+        }
+    }
+}
+
+// Vertices of a primitive as read by an importer. Normals and tangents the file doesn't
+// have are generated when the primitive is pushed.
+#[derive(Default)]
+pub(super) struct PrimitiveData {
+    pub positions: Vec<Vec4>,
+    // Indexed by set and then by vertex, with at least set 0
+    pub color_sets: Vec<Vec<Vec4>>,
+    pub uv_sets: Vec<Vec<Vec2>>,
+    pub normals: Option<Vec<Vec3>>,
+    pub tangents: Option<Vec<Vec4>>,
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<Vec4>,
+    pub morph_targets: Option<MorphTargets>,
+    pub indices: Vec<u32>,
+    pub topology: Topology,
+}
+
+impl PrimitiveData {
+    // Output vertex `i` becomes a copy of input vertex `vertices[i]`, normals are handled
+    // by `push`
+    fn unweld(&mut self, vertices: &[u32]) {
+        self.positions = unweld(&self.positions, vertices);
+        for set in self.color_sets.iter_mut() {
+            *set = unweld(set, vertices);
+        }
+        for set in self.uv_sets.iter_mut() {
+            *set = unweld(set, vertices);
+        }
+        if let Some(tangents) = &mut self.tangents {
+            *tangents = unweld(tangents, vertices);
+        }
+        self.joints = unweld(&self.joints, vertices);
+        self.weights = unweld(&self.weights, vertices);
+        if let Some(morph_targets) = &mut self.morph_targets {
+            morph_targets.unweld(vertices);
+        }
+    }
+
+    // Appends the primitive to the scene and returns its index
+    pub fn push(
+        mut self,
+        material_idx: u32,
+        normal_generation: NormalGeneration,
+        compiled_scene: &mut CompiledScene,
+    ) -> u32 {
+        let is_triangles = self.topology == Topology::Triangles;
+        let mut normals = match self.normals.take() {
+            Some(normals) => normals,
+            // Points and lines have no surface to take normals from
+            None if !is_triangles => vec![Vec3::new(0.0, 0.0, 0.0); self.positions.len()],
+            None => match normal_generation {
+                NormalGeneration::Flat => {
+                    let indices = std::mem::take(&mut self.indices);
+                    self.unweld(&indices);
+                    self.indices = (0..self.positions.len() as u32).collect();
+                    flat_normals(&self.positions)
+                }
+                NormalGeneration::Smooth => smooth_normals(&self.positions, &self.indices),
+            },
+        };
+
+        // Tangents are generated when the material has a normal texture, and follow the uvs
+        // it is sampled with
+        let normal_uv_set = compiled_scene.materials[material_idx as usize]
+            .normal_texture
+            .as_ref()
+            .map(|normal| normal.texture.tex_coord as usize);
+        let mut tangents = match (self.tangents.take(), normal_uv_set) {
+            (Some(tangents), _) => tangents,
+            (None, Some(uv_set)) if is_triangles => {
+                let uvs = self.uv_sets.get(uv_set).unwrap_or(&self.uv_sets[0]);
+                let generated = generate_tangents(&self.positions, &normals, uvs, &self.indices);
+                if generated.vertices.len() > self.positions.len() {
+                    self.unweld(&generated.vertices);
+                    normals = unweld(&normals, &generated.vertices);
+                }
+                self.indices = generated.indices;
+                generated.tangents
+            }
+            _ => vec![Vec4::new(0.0, 0.0, 0.0, 0.0); self.positions.len()],
+        };
+
+        let base_index = compiled_scene.positions.len() as u32;
+        for idx in &mut self.indices {
+            *idx += base_index;
+        }
+        let morph_targets_idx = self.morph_targets.map(|morph_targets| {
+            compiled_scene.morph_targets.push(morph_targets);
+            compiled_scene.morph_targets.len() as u32 - 1
+        });
+        compiled_scene.primitives.push(Primitive {
+            start_idx: compiled_scene.indices.len() as u32,
+            num_indices: self.indices.len() as u32,
+            start_vertex: base_index,
+            num_vertices: self.positions.len() as u32,
+            material_idx,
+            topology: self.topology,
+            morph_targets_idx,
+            aabb: Aabb::from_points(&self.positions),
+            bounding_sphere: BoundingSphere::from_points(&self.positions),
+        });
+
+        append_vertex_sets(
+            &mut compiled_scene.color_sets,
+            self.color_sets,
+            base_index as usize,
+            self.positions.len(),
+            Vec4::new(1.0, 1.0, 1.0, 1.0),
+        );
+        append_vertex_sets(
+            &mut compiled_scene.uv_sets,
+            self.uv_sets,
+            base_index as usize,
+            self.positions.len(),
+            Vec2::new(0.0, 0.0),
+        );
+        compiled_scene.positions.append(&mut self.positions);
+        compiled_scene.normals.append(&mut normals);
+        compiled_scene.tangents.append(&mut tangents);
+        compiled_scene.joints.append(&mut self.joints);
+        compiled_scene.weights.append(&mut self.weights);
+        compiled_scene.indices.append(&mut self.indices);
+
+        compiled_scene.primitives.len() as u32 - 1
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct CompiledScene {
     pub positions: Vec<Vec4>,
-    pub colors: Vec<Vec4>,
-    pub uvs: Vec<Vec2>,
+    // Indexed by set and then by vertex, like COLOR_n and TEXCOORD_n
+    pub color_sets: Vec<Vec<Vec4>>,
+    pub uv_sets: Vec<Vec<Vec2>>,
     pub normals: Vec<Vec3>,
+    pub tangents: Vec<Vec4>,
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<Vec4>,
     pub indices: Vec<u32>,
     pub materials: Vec<Material>,
+    pub primitives: Vec<Primitive>,
     pub mesh_draws: Vec<MeshDraw>,
     pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+    pub skins: Vec<Skin>,
+    pub morph_targets: Vec<MorphTargets>,
+    pub animations: Vec<Animation>,
+    // Every node in the document, indexed like the gltf nodes
+    pub graph: SceneGraph,
+}
+
+impl CompiledScene {
+    // Skinned vertices are already in world space, so their draws aren't transformed
+    pub fn transform_matrix(&self, mesh_draw: &MeshDraw) -> Mat4 {
+        if mesh_draw.skin_idx.is_some() {
+            Mat4::identity()
+        } else {
+            self.graph.world_transform(mesh_draw.node_idx as usize)
+        }
+    }
+
+    // World space bounds of a draw. Skinned and morphed draws use their rest pose bounds.
+    pub fn world_aabb(&self, mesh_draw: &MeshDraw) -> Aabb {
+        self.primitives[mesh_draw.primitive_idx as usize]
+            .aabb
+            .transformed(self.transform_matrix(mesh_draw))
+    }
+
+    pub fn world_bounding_sphere(&self, mesh_draw: &MeshDraw) -> BoundingSphere {
+        self.primitives[mesh_draw.primitive_idx as usize]
+            .bounding_sphere
+            .transformed(self.transform_matrix(mesh_draw))
+    }
+
+    // Bounds of every draw in the scene, empty if there are none
+    pub fn aabb(&self) -> Aabb {
+        self.mesh_draws
+            .iter()
+            .fold(Aabb::default(), |aabb, mesh_draw| {
+                aabb.union(&self.world_aabb(mesh_draw))
+            })
+    }
+
+    // Skinned draws are never culled, as their rest pose bounds don't follow the joints
+    pub fn visible_draws<'a>(
+        &'a self,
+        frustum: &'a Frustum,
+    ) -> impl Iterator<Item = &'a MeshDraw> + 'a {
+        self.mesh_draws.iter().filter(move |mesh_draw| {
+            mesh_draw.skin_idx.is_some()
+                || (frustum.intersects_sphere(&self.world_bounding_sphere(mesh_draw))
+                    && frustum.intersects_aabb(&self.world_aabb(mesh_draw)))
+        })
+    }
+
+    // Propagates edits made to the graph, moving the cameras and lights attached to
+    // the nodes that changed. Draws pick up their new transforms through `transform_matrix`.
+    pub fn update_transforms(&mut self) {
+        for idx in self.graph.update() {
+            let node = self.graph.node(idx);
+            let transform = self.graph.world_transform(idx);
+            if let Some(camera) = node.camera {
+                self.cameras[camera].set_transform(transform);
+            }
+            if let Some(light) = node.light {
+                self.lights[light].set_transform(transform);
+            }
+        }
+    }
+
+    // Returns the scene's positions and normals deformed by the given node world transforms.
+    pub fn skinned_vertices(&self, node_transforms: &[Mat4]) -> (Vec<Vec4>, Vec<Vec3>) {
+        let mut positions = self.positions.clone();
+        let mut normals = self.normals.clone();
+        self.apply_skinning(node_transforms, &mut positions, &mut normals);
+        (positions, normals)
+    }
+
+    // Returns the scene's positions and normals with every draw's morph targets blended in.
+    // `node_weights` overrides the weights of a node's draws when it isn't empty.
+    pub fn morphed_vertices(&self, node_weights: &[Vec<f32>]) -> (Vec<Vec4>, Vec<Vec3>) {
+        let mut positions = self.positions.clone();
+        let mut normals = self.normals.clone();
+        self.apply_morph_targets(node_weights, &mut positions, &mut normals);
+        (positions, normals)
+    }
+
+    // Vertices are shared between instances, so a primitive drawn several times is only
+    // deformed by its first draw
+    pub fn apply_skinning(
+        &self,
+        node_transforms: &[Mat4],
+        positions: &mut [Vec4],
+        normals: &mut [Vec3],
+    ) {
+        let joint_matrices = self
+            .skins
+            .iter()
+            .map(|skin| skin.joint_matrices(node_transforms))
+            .collect::<Vec<_>>();
+        let mut skinned = vec![false; self.primitives.len()];
+        for mesh_draw in &self.mesh_draws {
+            if let Some(skin_idx) = mesh_draw.skin_idx {
+                if std::mem::replace(&mut skinned[mesh_draw.primitive_idx as usize], true) {
+                    continue;
+                }
+                let joint_matrices = &joint_matrices[skin_idx as usize];
+                let primitive = &self.primitives[mesh_draw.primitive_idx as usize];
+                let start = primitive.start_vertex as usize;
+                let end = start + primitive.num_vertices as usize;
+                for idx in start..end {
+                    let (position, normal) = skin_vertex(
+                        positions[idx],
+                        normals[idx],
+                        self.joints[idx],
+                        self.weights[idx],
+                        joint_matrices,
+                    );
+                    positions[idx] = position;
+                    normals[idx] = normal;
+                }
+            }
+        }
+    }
+
+    // Morph targets have to be applied before skinning. Like skinning, each primitive is only
+    // morphed by its first draw.
+    pub fn apply_morph_targets(
+        &self,
+        node_weights: &[Vec<f32>],
+        positions: &mut [Vec4],
+        normals: &mut [Vec3],
+    ) {
+        let mut morphed = vec![false; self.primitives.len()];
+        for mesh_draw in &self.mesh_draws {
+            let primitive = &self.primitives[mesh_draw.primitive_idx as usize];
+            if let Some(morph_targets_idx) = primitive.morph_targets_idx {
+                if std::mem::replace(&mut morphed[mesh_draw.primitive_idx as usize], true) {
+                    continue;
+                }
+                let morph_targets = &self.morph_targets[morph_targets_idx as usize];
+                let node_idx = mesh_draw.node_idx as usize;
+                let weights = match node_weights.get(node_idx) {
+                    Some(weights) if !weights.is_empty() => weights,
+                    _ if !self.graph.node(node_idx).morph_weights.is_empty() => {
+                        &self.graph.node(node_idx).morph_weights
+                    }
+                    _ => &morph_targets.default_weights,
+                };
+                let start = primitive.start_vertex as usize;
+                let end = start + primitive.num_vertices as usize;
+                morph_targets.apply(
+                    weights,
+                    &mut positions[start..end],
+                    &mut normals[start..end],
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{
+        graph::SceneNode,
+        morph::{MorphTarget, MorphTargets},
+        Transform,
+    };
+
+    // One skinned and morphed triangle, drawn by two nodes
+    fn instanced_scene() -> CompiledScene {
+        let nodes = (0..2)
+            .map(|idx| SceneNode {
+                mesh_draws: vec![idx],
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        CompiledScene {
+            positions: vec![
+                Vec4::new(0.0, 0.0, 0.0, 1.0),
+                Vec4::new(1.0, 0.0, 0.0, 1.0),
+                Vec4::new(0.0, 1.0, 0.0, 1.0),
+            ],
+            normals: vec![Vec3::new(0.0, 0.0, 1.0); 3],
+            joints: vec![[0; 4]; 3],
+            weights: vec![Vec4::new(1.0, 0.0, 0.0, 0.0); 3],
+            indices: vec![0, 1, 2],
+            primitives: vec![Primitive {
+                num_indices: 3,
+                num_vertices: 3,
+                morph_targets_idx: Some(0),
+                ..Default::default()
+            }],
+            mesh_draws: (0..2)
+                .map(|node_idx| MeshDraw {
+                    node_idx,
+                    skin_idx: Some(0),
+                    ..Default::default()
+                })
+                .collect(),
+            skins: vec![Skin {
+                name: None,
+                joints: vec![0],
+                inverse_bind_matrices: vec![Mat4::identity()],
+                skeleton: None,
+            }],
+            morph_targets: vec![MorphTargets {
+                targets: vec![MorphTarget {
+                    positions: vec![Vec3::new(0.0, 0.0, 1.0); 3],
+                    normals: Vec::new(),
+                }],
+                default_weights: vec![1.0],
+            }],
+            graph: SceneGraph::new(nodes),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shared_primitives_are_morphed_once() {
+        let scene = instanced_scene();
+        let (positions, _) = scene.morphed_vertices(&[]);
+        for (morphed, position) in positions.iter().zip(&scene.positions) {
+            assert_eq!(morphed.z(), position.z() + 1.0);
+        }
+    }
+
+    #[test]
+    fn shared_primitives_are_skinned_once() {
+        let scene = instanced_scene();
+        let joint_transform = Transform {
+            translation: Vec3::new(0.0, 2.0, 0.0),
+            ..Default::default()
+        }
+        .matrix();
+        let (positions, _) = scene.skinned_vertices(&[joint_transform, Mat4::identity()]);
+        for (skinned, position) in positions.iter().zip(&scene.positions) {
+            assert_eq!(skinned.x(), position.x());
+            assert_eq!(skinned.y(), position.y() + 2.0);
+        }
+    }
+
+    #[test]
+    fn scene_aabb_covers_every_draw() {
+        let mut scene = instanced_scene();
+        for mesh_draw in &mut scene.mesh_draws {
+            mesh_draw.skin_idx = None;
+        }
+        scene.primitives[0].aabb = Aabb::from_points(&scene.positions);
+        scene.graph.set_transform(
+            1,
+            Transform {
+                translation: Vec3::new(5.0, 0.0, 0.0),
+                ..Default::default()
+            },
+        );
+        scene.update_transforms();
+
+        let aabb = scene.aabb();
+        assert_eq!([aabb.min.x(), aabb.min.y(), aabb.min.z()], [0.0, 0.0, 0.0]);
+        assert_eq!([aabb.max.x(), aabb.max.y(), aabb.max.z()], [6.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn empty_scene_has_an_empty_aabb() {
+        assert!(CompiledScene::default().aabb().is_empty());
+    }
 }
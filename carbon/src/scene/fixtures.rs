@@ -0,0 +1,209 @@
+// Small glTF, GLB, PNG and KTX2 files built in memory for the scene tests
+use serde_json::{json, Value};
+
+pub const UNSIGNED_SHORT: u32 = 5123;
+pub const FLOAT: u32 = 5126;
+
+// A glTF document whose buffer views all live in one binary buffer
+pub struct Document {
+    pub json: Value,
+    pub bin: Vec<u8>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self {
+            json: json!({ "asset": { "version": "2.0" } }),
+            bin: Vec::new(),
+        }
+    }
+
+    // Appends to the top-level array `key`, returning the new element's index
+    pub fn push(&mut self, key: &str, value: Value) -> usize {
+        let array = self.json[key].as_array_mut();
+        match array {
+            Some(array) => {
+                array.push(value);
+                array.len() - 1
+            }
+            None => {
+                self.json[key] = json!([value]);
+                0
+            }
+        }
+    }
+
+    pub fn push_view(&mut self, bytes: &[u8]) -> usize {
+        let view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        self.bin.extend_from_slice(bytes);
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        self.push("bufferViews", view)
+    }
+
+    // Float accessor of `components` floats per element, with the bounds positions need
+    pub fn push_floats(&mut self, values: &[f32], components: usize) -> usize {
+        let bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&bytes);
+        let mut min = vec![f32::MAX; components];
+        let mut max = vec![f32::MIN; components];
+        for element in values.chunks_exact(components) {
+            for (idx, value) in element.iter().enumerate() {
+                min[idx] = min[idx].min(*value);
+                max[idx] = max[idx].max(*value);
+            }
+        }
+        let ty = ["SCALAR", "VEC2", "VEC3", "VEC4"][components - 1];
+        self.push(
+            "accessors",
+            json!({
+                "bufferView": view,
+                "componentType": FLOAT,
+                "count": values.len() / components,
+                "type": ty,
+                "min": min,
+                "max": max,
+            }),
+        )
+    }
+
+    pub fn push_indices(&mut self, indices: &[u16]) -> usize {
+        let bytes = indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&bytes);
+        self.push(
+            "accessors",
+            json!({
+                "bufferView": view,
+                "componentType": UNSIGNED_SHORT,
+                "count": indices.len(),
+                "type": "SCALAR",
+            }),
+        )
+    }
+
+    fn json_with_buffer(&self, uri: Option<String>) -> Value {
+        let mut json = self.json.clone();
+        if !self.bin.is_empty() {
+            let mut buffer = json!({ "byteLength": self.bin.len() });
+            if let Some(uri) = uri {
+                buffer["uri"] = json!(uri);
+            }
+            json["buffers"] = json!([buffer]);
+        }
+        json
+    }
+
+    // Binary container with the buffer as its BIN chunk
+    pub fn to_glb(&self) -> Vec<u8> {
+        let mut json = serde_json::to_vec(&self.json_with_buffer(None)).unwrap();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut chunks = Vec::new();
+        chunks.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        chunks.extend_from_slice(b"JSON");
+        chunks.extend_from_slice(&json);
+        if !self.bin.is_empty() {
+            chunks.extend_from_slice(&(self.bin.len() as u32).to_le_bytes());
+            chunks.extend_from_slice(b"BIN\0");
+            chunks.extend_from_slice(&self.bin);
+        }
+
+        let mut glb = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&(12 + chunks.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&chunks);
+        glb
+    }
+
+    // Json file with the buffer embedded as a data uri
+    pub fn to_gltf(&self) -> Vec<u8> {
+        let uri = data_uri("application/octet-stream", &self.bin);
+        serde_json::to_vec(&self.json_with_buffer(Some(uri))).unwrap()
+    }
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn data_uri(mime_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", mime_type, base64::encode(bytes))
+}
+
+pub fn png(width: u32, height: u32, pixel: [u8; 4]) -> Vec<u8> {
+    let image = image::RgbaImage::from_pixel(width, height, image::Rgba(pixel));
+    let mut bytes = Vec::new();
+    image::DynamicImage::ImageRgba8(image)
+        .write_to(
+            &mut std::io::Cursor::new(&mut bytes),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    bytes
+}
+
+// Single level 2D texture, `vk_format` 0 (UNDEFINED) is what UASTC files use
+pub fn ktx2(vk_format: u32, width: u32, height: u32, level: &[u8]) -> Vec<u8> {
+    const HEADER_LENGTH: u64 = 80;
+    const LEVEL_INDEX_LENGTH: u64 = 24;
+
+    let mut bytes = vec![
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+    // Format, type size, width, height, depth, layers, faces, levels and supercompression
+    for value in [vk_format, 1, width, height, 0, 0, 1, 1, 0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    // No data format descriptor, key/value data or supercompression data
+    bytes.resize(HEADER_LENGTH as usize, 0);
+    for value in [
+        HEADER_LENGTH + LEVEL_INDEX_LENGTH,
+        level.len() as u64,
+        level.len() as u64,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(level);
+    bytes
+}
+
+// A triangle in the z = 0 plane using material 0, which samples texture 0 when the caller
+// adds one
+pub fn textured_triangle() -> Document {
+    let mut document = Document::new();
+    let positions = document.push_floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], 3);
+    let uvs = document.push_floats(&[0.0, 1.0, 1.0, 1.0, 0.0, 0.0], 2);
+    let indices = document.push_indices(&[0, 1, 2]);
+    document.push(
+        "meshes",
+        json!({
+            "primitives": [{
+                "attributes": { "POSITION": positions, "TEXCOORD_0": uvs },
+                "indices": indices,
+                "material": 0,
+            }],
+        }),
+    );
+    document.push(
+        "materials",
+        json!({ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }),
+    );
+    document.push("nodes", json!({ "mesh": 0 }));
+    document.push("scenes", json!({ "nodes": [0] }));
+    document.json["scene"] = json!(0);
+    document
+}
@@ -0,0 +1,371 @@
+use math::vec::{Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+
+// The gltf spec requires flat normals when a primitive has none
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalGeneration {
+    // One normal per triangle, which splits every vertex shared between triangles
+    #[default]
+    Flat,
+    // Normals of the triangles around a vertex, weighted by the angle at that corner
+    Smooth,
+}
+
+fn xyz(v: Vec4) -> Vec3 {
+    Vec3::new(v.x(), v.y(), v.z())
+}
+
+fn normalized_or_zero(v: Vec3) -> Vec3 {
+    if v.dot(&v) > 0.0 {
+        v.normalized()
+    } else {
+        v
+    }
+}
+
+// Triangles repeating a vertex are dropped, strips use them to restart
+pub fn triangle_strip_to_list(indices: &[u32]) -> Vec<u32> {
+    indices
+        .windows(3)
+        .enumerate()
+        .map(|(idx, triangle)| {
+            // Every other triangle is flipped to keep the winding consistent
+            if idx % 2 == 0 {
+                [triangle[0], triangle[1], triangle[2]]
+            } else {
+                [triangle[0], triangle[2], triangle[1]]
+            }
+        })
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect()
+}
+
+pub fn triangle_fan_to_list(indices: &[u32]) -> Vec<u32> {
+    match indices.split_first() {
+        Some((center, rest)) => rest
+            .windows(2)
+            .flat_map(|edge| [edge[0], edge[1], *center])
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+pub fn line_strip_to_list(indices: &[u32]) -> Vec<u32> {
+    indices.windows(2).flatten().copied().collect()
+}
+
+pub fn line_loop_to_list(indices: &[u32]) -> Vec<u32> {
+    let mut lines = line_strip_to_list(indices);
+    if let (Some(first), Some(last)) = (indices.first(), indices.last()) {
+        if indices.len() > 2 {
+            lines.extend([*last, *first]);
+        }
+    }
+    lines
+}
+
+pub fn unweld<T: Copy>(data: &[T], indices: &[u32]) -> Vec<T> {
+    indices.iter().map(|idx| data[*idx as usize]).collect()
+}
+
+// Expects un-indexed triangles, as produced by `unweld`
+pub fn flat_normals(positions: &[Vec4]) -> Vec<Vec3> {
+    let mut normals = Vec::with_capacity(positions.len());
+    for triangle in positions.chunks(3) {
+        let normal = if let [a, b, c] = triangle {
+            let (a, b, c) = (xyz(*a), xyz(*b), xyz(*c));
+            normalized_or_zero((b - a).cross(&(c - a)))
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+        for _ in triangle {
+            normals.push(normal);
+        }
+    }
+    normals
+}
+
+pub fn smooth_normals(positions: &[Vec4], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let corners = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let [a, b, c] = corners.map(|idx| xyz(positions[idx]));
+        let face_normal = normalized_or_zero((b - a).cross(&(c - a)));
+        for (corner, (prev, next)) in corners.iter().zip([(c, b), (a, c), (b, a)]) {
+            let position = xyz(positions[*corner]);
+            let to_prev = normalized_or_zero(prev - position);
+            let to_next = normalized_or_zero(next - position);
+            let angle = to_prev.dot(&to_next).clamp(-1.0, 1.0).acos();
+            normals[*corner] += face_normal * angle;
+        }
+    }
+    normals.into_iter().map(normalized_or_zero).collect()
+}
+
+struct TangentGeometry<'a> {
+    positions: &'a [Vec4],
+    normals: &'a [Vec3],
+    uvs: &'a [Vec2],
+    indices: &'a [u32],
+    // One per face corner, in index order
+    corner_tangents: Vec<[f32; 4]>,
+}
+
+impl TangentGeometry<'_> {
+    fn index(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let position = self.positions[self.index(face, vert)];
+        [position.x(), position.y(), position.z()]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        let normal = self.normals[self.index(face, vert)];
+        [normal.x(), normal.y(), normal.z()]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = self.uvs[self.index(face, vert)];
+        [uv.x(), uv.y()]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corner_tangents[face * 3 + vert] = tangent;
+    }
+}
+
+// Used for every vertex when MikkTSpace fails
+const DEFAULT_TANGENT: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+// MikkTSpace can give the corners sharing a vertex different tangents, those vertices are
+// copied to the end. `vertices` holds the input vertex each output vertex copies, so the
+// other attributes can follow with `unweld`.
+#[derive(Debug)]
+pub struct GeneratedTangents {
+    // Tangents are (x, y, z, w), with w holding the handedness of the bitangent
+    pub tangents: Vec<Vec4>,
+    pub vertices: Vec<u32>,
+    pub indices: Vec<u32>,
+    // Set when MikkTSpace failed and every vertex got the default tangent
+    pub fallback: bool,
+}
+
+pub fn generate_tangents(
+    positions: &[Vec4],
+    normals: &[Vec3],
+    uvs: &[Vec2],
+    indices: &[u32],
+) -> GeneratedTangents {
+    let mut geometry = TangentGeometry {
+        positions,
+        normals,
+        uvs,
+        indices,
+        corner_tangents: vec![DEFAULT_TANGENT; indices.len()],
+    };
+    if !mikktspace::generate_tangents(&mut geometry) {
+        return GeneratedTangents {
+            tangents: vec![DEFAULT_TANGENT.into(); positions.len()],
+            vertices: (0..positions.len() as u32).collect(),
+            indices: indices.to_vec(),
+            fallback: true,
+        };
+    }
+    split_by_tangent(positions.len(), indices, &geometry.corner_tangents)
+}
+
+// Vertices keep their index for the first tangent they get, and are copied for every other one
+fn split_by_tangent(
+    num_vertices: usize,
+    indices: &[u32],
+    corner_tangents: &[[f32; 4]],
+) -> GeneratedTangents {
+    let mut vertices = (0..num_vertices as u32).collect::<Vec<_>>();
+    let mut tangents = vec![None; num_vertices];
+    let mut copies = HashMap::<u32, Vec<u32>>::new();
+    let mut split_indices = Vec::with_capacity(indices.len());
+    for (idx, tangent) in indices.iter().zip(corner_tangents) {
+        let vertex = match tangents[*idx as usize] {
+            None => {
+                tangents[*idx as usize] = Some(*tangent);
+                *idx
+            }
+            Some(existing) if existing == *tangent => *idx,
+            Some(_) => {
+                let copies = copies.entry(*idx).or_default();
+                match copies
+                    .iter()
+                    .find(|copy| tangents[**copy as usize] == Some(*tangent))
+                {
+                    Some(copy) => *copy,
+                    None => {
+                        let copy = vertices.len() as u32;
+                        vertices.push(*idx);
+                        tangents.push(Some(*tangent));
+                        copies.push(copy);
+                        copy
+                    }
+                }
+            }
+        };
+        split_indices.push(vertex);
+    }
+    GeneratedTangents {
+        // Vertices no triangle uses keep a zero tangent
+        tangents: tangents
+            .into_iter()
+            .map(|tangent| tangent.unwrap_or_default().into())
+            .collect(),
+        vertices,
+        indices: split_indices,
+        fallback: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TANGENT_A: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const TANGENT_B: [f32; 4] = [-1.0, 0.0, 0.0, -1.0];
+
+    #[test]
+    fn corners_with_matching_tangents_share_vertices() {
+        let split = split_by_tangent(4, &[0, 1, 2, 0, 2, 3], &[TANGENT_A; 6]);
+        assert_eq!(split.vertices, [0, 1, 2, 3]);
+        assert_eq!(split.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn corners_with_diverging_tangents_split_vertices() {
+        let corner_tangents = [
+            TANGENT_A, TANGENT_A, TANGENT_A, TANGENT_B, TANGENT_B, TANGENT_B,
+        ];
+        let split = split_by_tangent(4, &[0, 1, 2, 0, 2, 3], &corner_tangents);
+        assert_eq!(split.vertices, [0, 1, 2, 3, 0, 2]);
+        assert_eq!(split.indices, [0, 1, 2, 4, 5, 3]);
+        for (idx, tangent) in split.indices.iter().zip(corner_tangents) {
+            assert_eq!(split.tangents[*idx as usize].x(), tangent[0]);
+        }
+    }
+
+    // Two triangles sharing an edge, with the uvs mirrored across it
+    #[test]
+    fn mirrored_uvs_get_their_own_tangents() {
+        let positions = [
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+            Vec4::new(-1.0, 0.0, 0.0, 1.0),
+        ];
+        let normals = [Vec3::new(0.0, 0.0, 1.0); 4];
+        let uvs = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(1.0, 0.0),
+        ];
+        let indices = [0, 1, 2, 0, 2, 3];
+        let generated = generate_tangents(&positions, &normals, &uvs, &indices);
+
+        // The shared edge's vertices are copied for the mirrored triangle
+        assert_eq!(generated.vertices.len(), 6);
+        assert_eq!(generated.tangents.len(), 6);
+        for (corner, idx) in generated.indices.iter().enumerate() {
+            let tangent = generated.tangents[*idx as usize];
+            let expected = if corner < 3 { 1.0 } else { -1.0 };
+            assert!((tangent.x() - expected).abs() < 1e-5, "{:?}", tangent);
+            let source = generated.vertices[*idx as usize];
+            assert_eq!(source, indices[corner]);
+        }
+    }
+
+    #[test]
+    fn failed_generation_falls_back_to_default_tangents() {
+        let positions = [Vec4::new(0.0, 0.0, 0.0, 1.0); 2];
+        let normals = [Vec3::new(0.0, 0.0, 1.0); 2];
+        let uvs = [Vec2::new(0.0, 0.0); 2];
+        // MikkTSpace fails without any triangles
+        let generated = generate_tangents(&positions, &normals, &uvs, &[]);
+        assert!(generated.fallback);
+        assert_eq!(generated.vertices, [0, 1]);
+        assert_eq!(generated.tangents[1].x(), DEFAULT_TANGENT[0]);
+    }
+
+    fn assert_normal(normal: Vec3, expected: [f32; 3]) {
+        let actual = [normal.x(), normal.y(), normal.z()];
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    // The +z, +x and +y faces of a unit cube, two triangles each, meeting at the origin
+    fn cube_corner() -> (Vec<Vec4>, Vec<u32>) {
+        let positions = vec![
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(-1.0, 0.0, 0.0, 1.0),
+            Vec4::new(-1.0, -1.0, 0.0, 1.0),
+            Vec4::new(0.0, -1.0, 0.0, 1.0),
+            Vec4::new(0.0, -1.0, -1.0, 1.0),
+            Vec4::new(0.0, 0.0, -1.0, 1.0),
+            Vec4::new(-1.0, 0.0, -1.0, 1.0),
+        ];
+        let indices = vec![0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 5, 0, 5, 6, 0, 6, 1];
+        (positions, indices)
+    }
+
+    #[test]
+    fn flat_normals_face_out_of_each_triangle() {
+        let (positions, indices) = cube_corner();
+        let normals = flat_normals(&unweld(&positions, &indices));
+        assert_eq!(normals.len(), indices.len());
+        let faces = [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        for (corner, normal) in normals.iter().enumerate() {
+            assert_normal(*normal, faces[corner / 6]);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_average_the_faces_around_a_vertex() {
+        let (positions, indices) = cube_corner();
+        let normals = smooth_normals(&positions, &indices);
+        // Every face meets the shared corner at the same total angle
+        let diagonal = 1.0 / 3.0f32.sqrt();
+        assert_normal(normals[0], [diagonal; 3]);
+        // Vertices on a single face keep its normal
+        assert_normal(normals[2], [0.0, 0.0, 1.0]);
+        assert_normal(normals[4], [1.0, 0.0, 0.0]);
+        assert_normal(normals[6], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn smooth_normals_on_a_shared_vertex_quad_match_its_plane() {
+        let positions = [
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(1.0, 1.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+        ];
+        let normals = smooth_normals(&positions, &[0, 1, 2, 0, 2, 3]);
+        for normal in normals {
+            assert_normal(normal, [0.0, 0.0, 1.0]);
+        }
+    }
+}
@@ -0,0 +1,566 @@
+use super::{
+    compiled_scene::{AlphaMode, CompiledScene, Material, Primitive, Topology},
+    gltf_scene::GltfSceneError,
+    transform::Transform,
+};
+use crate::camera::{Camera, CameraType};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, path::Path};
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// Writes `path` as a .gltf and its geometry to a .bin next to it
+pub fn write_gltf(scene: &CompiledScene, path: impl AsRef<Path>) -> Result<(), GltfSceneError> {
+    let path = path.as_ref();
+    let bin_path = path.with_extension("bin");
+    let uri = bin_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    let (json, bin) = export(scene, uri.as_deref());
+    std::fs::write(path, serde_json::to_vec_pretty(&json)?)?;
+    if !bin.is_empty() {
+        std::fs::write(bin_path, bin)?;
+    }
+    Ok(())
+}
+
+pub fn write_glb(scene: &CompiledScene, path: impl AsRef<Path>) -> Result<(), GltfSceneError> {
+    std::fs::write(path, to_glb(scene)?)?;
+    Ok(())
+}
+
+pub fn to_glb(scene: &CompiledScene) -> Result<Vec<u8>, GltfSceneError> {
+    let (json, mut bin) = export(scene, None);
+    let mut json = serde_json::to_vec(&json)?;
+    // Chunks are 4 byte aligned, json is padded with spaces and binary data with zeros
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    // The binary chunk is left out when there is no geometry
+    let bin_chunk_length = if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let length = 12 + 8 + json.len() + bin_chunk_length;
+    let mut glb = Vec::with_capacity(length);
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(length as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json);
+    if !bin.is_empty() {
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+    }
+    Ok(glb)
+}
+
+#[derive(Default)]
+struct BufferBuilder {
+    data: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl BufferBuilder {
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.data.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    // `values` holds `components` floats per element, positions also need their bounds
+    fn push_floats(&mut self, values: &[f32], components: usize, with_bounds: bool) -> usize {
+        let bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&bytes, ARRAY_BUFFER);
+        let ty = ["SCALAR", "VEC2", "VEC3", "VEC4"][components - 1];
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": values.len() / components,
+            "type": ty,
+        });
+        if with_bounds {
+            let mut min = vec![f32::MAX; components];
+            let mut max = vec![f32::MIN; components];
+            for element in values.chunks_exact(components) {
+                for (idx, value) in element.iter().enumerate() {
+                    min[idx] = min[idx].min(*value);
+                    max[idx] = max[idx].max(*value);
+                }
+            }
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes = indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&bytes, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+fn insert_name(object: &mut Value, name: Option<&str>) {
+    if let Some(name) = name {
+        object["name"] = json!(name);
+    }
+}
+
+fn export_primitive(
+    scene: &CompiledScene,
+    primitive: &Primitive,
+    buffer: &mut BufferBuilder,
+) -> Value {
+    let vertices =
+        primitive.start_vertex as usize..(primitive.start_vertex + primitive.num_vertices) as usize;
+    let positions = scene.positions[vertices.clone()]
+        .iter()
+        .flat_map(|position| [position.x(), position.y(), position.z()])
+        .collect::<Vec<_>>();
+    let normals = scene.normals[vertices.clone()]
+        .iter()
+        .flat_map(|normal| [normal.x(), normal.y(), normal.z()])
+        .collect::<Vec<_>>();
+    let color_sets = scene
+        .color_sets
+        .iter()
+        .map(|set| {
+            set[vertices.clone()]
+                .iter()
+                .flat_map(|color| [color.x(), color.y(), color.z(), color.w()])
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let uv_sets = scene
+        .uv_sets
+        .iter()
+        .map(|set| {
+            set[vertices.clone()]
+                .iter()
+                .flat_map(|uv| [uv.x(), uv.y()])
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // Indices are stored relative to the whole scene's vertices
+    let indices = scene.indices
+        [primitive.start_idx as usize..(primitive.start_idx + primitive.num_indices) as usize]
+        .iter()
+        .map(|index| index - primitive.start_vertex)
+        .collect::<Vec<_>>();
+
+    let mut attributes = json!({
+        "POSITION": buffer.push_floats(&positions, 3, true),
+    });
+    // Points and lines without normals are compiled with zero normals, which glTF doesn't allow
+    if scene.normals[vertices]
+        .iter()
+        .all(|normal| normal.dot(normal) > 0.0)
+    {
+        attributes["NORMAL"] = json!(buffer.push_floats(&normals, 3, false));
+    }
+    for (set, colors) in color_sets.iter().enumerate() {
+        attributes[format!("COLOR_{}", set)] = json!(buffer.push_floats(colors, 4, false));
+    }
+    for (set, uvs) in uv_sets.iter().enumerate() {
+        attributes[format!("TEXCOORD_{}", set)] = json!(buffer.push_floats(uvs, 2, false));
+    }
+
+    json!({
+        "attributes": attributes,
+        "indices": buffer.push_indices(&indices),
+        "material": primitive.material_idx,
+        "mode": match primitive.topology {
+            Topology::Points => 0,
+            Topology::Lines => 1,
+            Topology::Triangles => 4,
+        },
+    })
+}
+
+fn export_material(material: &Material) -> Value {
+    let pbr = &material.metallic_roughness;
+    let color = pbr.base_color_factor;
+    let emissive = material.emissive_factor;
+    let mut value = json!({
+        "pbrMetallicRoughness": {
+            "baseColorFactor": [color.x(), color.y(), color.z(), color.w()],
+            "metallicFactor": pbr.metallic_factor,
+            "roughnessFactor": pbr.roughness_factor,
+        },
+        "emissiveFactor": [emissive.x(), emissive.y(), emissive.z()],
+        "alphaMode": match material.alpha_mode {
+            AlphaMode::Opaque => "OPAQUE",
+            AlphaMode::Mask => "MASK",
+            AlphaMode::Blend => "BLEND",
+        },
+        "doubleSided": material.double_sided,
+    });
+    if material.alpha_mode == AlphaMode::Mask {
+        value["alphaCutoff"] = json!(material.alpha_cutoff);
+    }
+    let mut extensions = Map::new();
+    if material.emissive_strength != 1.0 {
+        extensions.insert(
+            "KHR_materials_emissive_strength".to_owned(),
+            json!({ "emissiveStrength": material.emissive_strength }),
+        );
+    }
+    if material.unlit {
+        extensions.insert("KHR_materials_unlit".to_owned(), json!({}));
+    }
+    if material.transmission_factor != 0.0 {
+        extensions.insert(
+            "KHR_materials_transmission".to_owned(),
+            json!({ "transmissionFactor": material.transmission_factor }),
+        );
+    }
+    if !extensions.is_empty() {
+        value["extensions"] = Value::Object(extensions);
+    }
+    insert_name(&mut value, material.name.as_deref());
+    value
+}
+
+fn export_camera(camera: &Camera) -> Value {
+    let mut value = match camera.ty() {
+        CameraType::Orthographic(data) => json!({
+            "type": "orthographic",
+            "orthographic": {
+                "xmag": data.x_mag,
+                "ymag": data.y_mag,
+                "znear": data.near,
+                "zfar": data.far,
+            },
+        }),
+        CameraType::Perspective(data) => {
+            let mut perspective = json!({
+                "yfov": data.y_fov,
+                "znear": data.z_near,
+            });
+            if let Some(aspect_ratio) = data.aspect_ratio {
+                perspective["aspectRatio"] = json!(aspect_ratio);
+            }
+            if let Some(z_far) = data.z_far {
+                perspective["zfar"] = json!(z_far);
+            }
+            json!({
+                "type": "perspective",
+                "perspective": perspective,
+            })
+        }
+    };
+    insert_name(&mut value, camera.name());
+    value
+}
+
+fn export_transform(node: &mut Value, transform: &Transform) {
+    let (t, r, s) = (transform.translation, transform.rotation, transform.scale);
+    node["translation"] = json!([t.x(), t.y(), t.z()]);
+    node["rotation"] = json!([r.x(), r.y(), r.z(), r.w()]);
+    node["scale"] = json!([s.x(), s.y(), s.z()]);
+}
+
+// Every primitive becomes a mesh primitive, and the draws of each node are grouped into
+// one mesh so that instancing survives the round trip. Only the nodes of the compiled scene
+// are written, nodes of the document's other scenes are left out.
+// Textures need the scene's images, which `CompiledScene` doesn't hold, so materials are
+// written with their factors and without their textures for now.
+// TODO: Export textures, skins, morph targets, animations and lights
+fn export(scene: &CompiledScene, buffer_uri: Option<&str>) -> (Value, Vec<u8>) {
+    let mut buffer = BufferBuilder::default();
+    let primitives = scene
+        .primitives
+        .iter()
+        .map(|primitive| export_primitive(scene, primitive, &mut buffer))
+        .collect::<Vec<_>>();
+
+    let mut meshes = Vec::new();
+    let mut mesh_indices = HashMap::<Vec<u32>, usize>::new();
+    let mut node_primitives = vec![Vec::new(); scene.graph.nodes().len()];
+    for mesh_draw in &scene.mesh_draws {
+        node_primitives[mesh_draw.node_idx as usize].push(mesh_draw.primitive_idx);
+    }
+
+    // Nodes reachable from the scene's roots keep their order, `node_indices` maps graph
+    // indices to exported ones
+    let mut in_scene = vec![false; scene.graph.nodes().len()];
+    let mut stack = scene.graph.roots().to_vec();
+    while let Some(idx) = stack.pop() {
+        in_scene[idx] = true;
+        stack.extend(&scene.graph.node(idx).children);
+    }
+    let exported_nodes = (0..in_scene.len())
+        .filter(|idx| in_scene[*idx])
+        .collect::<Vec<_>>();
+    let mut node_indices = vec![None; in_scene.len()];
+    for (exported_idx, idx) in exported_nodes.iter().enumerate() {
+        node_indices[*idx] = Some(exported_idx);
+    }
+
+    let mut cameras = Vec::new();
+    let nodes = exported_nodes
+        .iter()
+        .map(|idx| {
+            let node = scene.graph.node(*idx);
+            let primitive_indices = &node_primitives[*idx];
+            let mut value = Value::Object(Map::new());
+            insert_name(&mut value, node.name.as_deref());
+            export_transform(&mut value, &node.transform);
+            if !node.children.is_empty() {
+                let children = node
+                    .children
+                    .iter()
+                    .filter_map(|child| node_indices[*child])
+                    .collect::<Vec<_>>();
+                value["children"] = json!(children);
+            }
+            if !node.morph_weights.is_empty() {
+                value["weights"] = json!(node.morph_weights);
+            }
+            if !primitive_indices.is_empty() {
+                let mesh = *mesh_indices
+                    .entry(primitive_indices.clone())
+                    .or_insert_with(|| {
+                        meshes.push(json!({
+                            "primitives": primitive_indices
+                                .iter()
+                                .map(|idx| primitives[*idx as usize].clone())
+                                .collect::<Vec<_>>(),
+                        }));
+                        meshes.len() - 1
+                    });
+                value["mesh"] = json!(mesh);
+            }
+            if let Some(camera) = node.camera {
+                cameras.push(export_camera(&scene.cameras[camera]));
+                value["camera"] = json!(cameras.len() - 1);
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+
+    let mut buffers = Vec::new();
+    if !buffer.data.is_empty() {
+        let mut buffer_json = json!({ "byteLength": buffer.data.len() });
+        if let Some(uri) = buffer_uri {
+            buffer_json["uri"] = json!(uri);
+        }
+        buffers.push(buffer_json);
+    }
+    let materials = scene
+        .materials
+        .iter()
+        .map(export_material)
+        .collect::<Vec<_>>();
+    let mut extensions_used = materials
+        .iter()
+        .filter_map(|material| material["extensions"].as_object())
+        .flat_map(|extensions| extensions.keys().cloned())
+        .collect::<Vec<_>>();
+    extensions_used.sort();
+    extensions_used.dedup();
+
+    let scene_nodes = scene
+        .graph
+        .roots()
+        .iter()
+        .filter_map(|idx| node_indices[*idx])
+        .collect::<Vec<_>>();
+    let mut json = json!({
+        "asset": { "version": "2.0", "generator": "carbon" },
+        "extensionsUsed": extensions_used,
+        "scene": 0,
+        "scenes": [{ "nodes": scene_nodes }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "cameras": cameras,
+        "materials": materials,
+        "accessors": buffer.accessors,
+        "bufferViews": buffer.buffer_views,
+        "buffers": buffers,
+    });
+    // glTF doesn't allow empty top level arrays
+    if let Value::Object(object) = &mut json {
+        object.retain(|_, value| !value.as_array().is_some_and(|array| array.is_empty()));
+    }
+    (json, buffer.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{
+        fixtures::{self, Document},
+        GltfScene,
+    };
+
+    // A parent and a child instancing the same triangle in the default scene, and a third
+    // node in a second scene
+    fn two_scenes() -> Document {
+        let mut document = Document::new();
+        let positions = document.push_floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], 3);
+        let normals = document.push_floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0], 3);
+        let uvs = document.push_floats(&[0.0, 1.0, 1.0, 1.0, 0.0, 0.0], 2);
+        let indices = document.push_indices(&[0, 1, 2]);
+        document.push(
+            "meshes",
+            json!({
+                "primitives": [{
+                    "attributes": { "POSITION": positions, "NORMAL": normals, "TEXCOORD_0": uvs },
+                    "indices": indices,
+                    "material": 0,
+                }],
+            }),
+        );
+        document.push(
+            "materials",
+            json!({
+                "name": "leaf",
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [0.25, 0.5, 0.75, 1.0],
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 0.5,
+                },
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.25,
+                "doubleSided": true,
+            }),
+        );
+        document.push(
+            "nodes",
+            json!({ "name": "parent", "mesh": 0, "translation": [1.0, 2.0, 3.0], "children": [1] }),
+        );
+        document.push(
+            "nodes",
+            json!({ "name": "child", "mesh": 0, "rotation": [0.0, 0.6, 0.0, 0.8], "scale": [2.0, 2.0, 2.0] }),
+        );
+        document.push("nodes", json!({ "name": "other", "mesh": 0 }));
+        document.push("scenes", json!({ "nodes": [0] }));
+        document.push("scenes", json!({ "nodes": [2] }));
+        document.json["scene"] = json!(0);
+        document
+    }
+
+    #[test]
+    fn round_trip() {
+        let scene = GltfScene::from_slice(&two_scenes().to_glb())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let exported = GltfScene::from_slice(&to_glb(&scene).unwrap())
+            .unwrap()
+            .compile()
+            .unwrap();
+
+        assert_eq!(
+            format!("{:?}", exported.positions),
+            format!("{:?}", scene.positions)
+        );
+        assert_eq!(
+            format!("{:?}", exported.normals),
+            format!("{:?}", scene.normals)
+        );
+        assert_eq!(
+            format!("{:?}", exported.uv_sets),
+            format!("{:?}", scene.uv_sets)
+        );
+        assert_eq!(exported.indices, scene.indices);
+        assert_eq!(
+            format!("{:?}", exported.mesh_draws),
+            format!("{:?}", scene.mesh_draws)
+        );
+
+        // Re-importing appends another default material after the exported ones
+        assert_eq!(
+            format!("{:?}", &exported.materials[..scene.materials.len()]),
+            format!("{:?}", scene.materials)
+        );
+
+        // The second scene's node is left out
+        let names = exported
+            .graph
+            .nodes()
+            .iter()
+            .map(|node| node.name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("parent"), Some("child")]);
+        assert_eq!(exported.graph.roots(), [0]);
+        for (exported_node, node) in exported.graph.nodes().iter().zip(scene.graph.nodes()) {
+            assert_eq!(exported_node.transform, node.transform);
+            assert_eq!(exported_node.children, node.children);
+        }
+        assert_eq!(
+            format!("{:?}", exported.graph.world_transforms()),
+            format!("{:?}", &scene.graph.world_transforms()[..2])
+        );
+    }
+
+    #[test]
+    fn textures_are_dropped() {
+        let mut document = fixtures::textured_triangle();
+        document.json["materials"][0]["pbrMetallicRoughness"]["baseColorFactor"] =
+            json!([0.5, 0.5, 0.5, 1.0]);
+        document.push(
+            "images",
+            json!({ "uri": fixtures::data_uri("image/png", &fixtures::png(1, 1, [255; 4])) }),
+        );
+        document.push("textures", json!({ "source": 0 }));
+        let scene = GltfScene::from_slice(&document.to_glb())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let exported = GltfScene::from_slice(&to_glb(&scene).unwrap())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let pbr = &exported.materials[0].metallic_roughness;
+        assert!(pbr.base_color_texture.is_none());
+        assert_eq!(pbr.base_color_factor.x(), 0.5);
+    }
+
+    #[test]
+    fn points_without_normals_leave_them_out() {
+        let mut document = Document::new();
+        let positions = document.push_floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 3);
+        document.push(
+            "meshes",
+            json!({ "primitives": [{ "attributes": { "POSITION": positions }, "mode": 0 }] }),
+        );
+        document.push("nodes", json!({ "mesh": 0 }));
+        document.push("scenes", json!({ "nodes": [0] }));
+        let scene = GltfScene::from_slice(&document.to_glb())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let (json, _) = export(&scene, None);
+        let attributes = &json["meshes"][0]["primitives"][0]["attributes"];
+        assert!(attributes.get("POSITION").is_some());
+        assert!(attributes.get("NORMAL").is_none());
+    }
+}
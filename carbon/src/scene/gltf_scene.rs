@@ -1,25 +1,71 @@
-use crate::camera::{Camera, CameraType, OrtographicData, PerspectiveData};
+use crate::camera::{Camera, CameraType, OrthographicFit, OrtographicData, PerspectiveData};
 
-use super::compiled_scene::{CompiledScene, Material, MeshDraw};
+use super::{
+    accessor::{read_floats, read_integers, AccessorFormat},
+    animation::Animation,
+    cache::{self, CachedScene},
+    compiled_scene::{CompiledScene, Light, Material, MeshDraw, PrimitiveData, Topology},
+    geometry::{
+        line_loop_to_list, line_strip_to_list, triangle_fan_to_list, triangle_strip_to_list,
+        NormalGeneration,
+    },
+    graph::{SceneGraph, SceneNode},
+    mipmap::{build_mip_chain, ColorSpace, MipFilter},
+    morph::MorphTargets,
+    skin::Skin,
+};
 use anyhow::Result;
 use bytes::Bytes;
 use math::{
     mat::Mat4,
     vec::{Vec2, Vec3, Vec4},
 };
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum GltfSceneError {
-    #[error("Binary buffers not yet supporterd")]
-    BinaryBuffer,
-    #[error("Image views not yet supporterd")]
-    ViewImage,
+    #[error(transparent)]
+    GltfError(#[from] gltf::Error),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    Base64Error(#[from] base64::DecodeError),
+    #[error(transparent)]
+    ImageError(#[from] image::ImageError),
+    #[error("Image has {actual} bytes of pixel data, expected {expected}")]
+    ImageLength { expected: usize, actual: usize },
+    #[error("Unsupported image color type {0:?}")]
+    UnsupportedImageFormat(image::ColorType),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    Ktx2Error(#[from] ktx2::ParseError),
+    #[error("Unsupported KTX2 texture: {0}")]
+    UnsupportedKtx2(String),
     #[error("Gltf file contained no default scene")]
     NoDefaultScene,
+    #[error("Gltf file contained no scene with index {0}")]
+    SceneIndexNotFound(usize),
+    #[error("Gltf file contained no scene named {0}")]
+    SceneNameNotFound(String),
+    #[error("Binary buffer referenced but no binary chunk was found")]
+    MissingBlob,
+    #[error("Buffer {index} expected {expected} bytes, but only {actual} were found")]
+    BufferLength {
+        index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Buffer view {0} reaches past the end of its buffer")]
+    BufferViewRange(usize),
+    #[error("Unsupported uri scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("External reference {0} can't be resolved without a base path")]
+    ExternalReference(String),
 }
 
 fn compile_gltf_node<F>(node: &gltf::scene::Node, f: &mut F, transform: Mat4)
@@ -36,177 +82,730 @@ where
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneSelector {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneInfo {
+    pub index: usize,
+    pub name: Option<String>,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeFormat {
+    // As named in the file, e.g. `TEXCOORD_1`
+    pub semantic: String,
+    // `None` for the primitive's own attributes
+    pub morph_target: Option<usize>,
+    pub format: AccessorFormat,
+    // Set for attributes `compile` doesn't read
+    pub ignored: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrimitiveReport {
+    pub mesh_index: usize,
+    pub mesh_name: Option<String>,
+    pub primitive_index: usize,
+    pub indices: Option<AccessorFormat>,
+    pub attributes: Vec<AttributeFormat>,
+}
+
+impl PrimitiveReport {
+    pub fn ignored(&self) -> impl Iterator<Item = &AttributeFormat> {
+        self.attributes.iter().filter(|attribute| attribute.ignored)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WrapMode {
+    ClampToEdge,
+    MirroredRepeat,
+    Repeat,
+}
+
+impl From<gltf::texture::WrappingMode> for WrapMode {
+    fn from(mode: gltf::texture::WrappingMode) -> Self {
+        match mode {
+            gltf::texture::WrappingMode::ClampToEdge => Self::ClampToEdge,
+            gltf::texture::WrappingMode::MirroredRepeat => Self::MirroredRepeat,
+            gltf::texture::WrappingMode::Repeat => Self::Repeat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerData {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    // `None` if the sampler should only ever read the base mip level
+    pub mipmap_mode: Option<Filter>,
+    pub wrap_s: WrapMode,
+    pub wrap_t: WrapMode,
+}
+
+impl SamplerData {
+    fn new(sampler: &gltf::texture::Sampler) -> Self {
+        use gltf::texture::{MagFilter, MinFilter};
+
+        // Filters are left up to the implementation when not specified, default to trilinear
+        let mag_filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => Filter::Nearest,
+            Some(MagFilter::Linear) | None => Filter::Linear,
+        };
+        let (min_filter, mipmap_mode) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (Filter::Nearest, None),
+            Some(MinFilter::Linear) => (Filter::Linear, None),
+            Some(MinFilter::NearestMipmapNearest) => (Filter::Nearest, Some(Filter::Nearest)),
+            Some(MinFilter::LinearMipmapNearest) => (Filter::Linear, Some(Filter::Nearest)),
+            Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, Some(Filter::Linear)),
+            Some(MinFilter::LinearMipmapLinear) | None => (Filter::Linear, Some(Filter::Linear)),
+        };
+        Self {
+            mag_filter,
+            min_filter,
+            mipmap_mode,
+            wrap_s: sampler.wrap_s().into(),
+            wrap_t: sampler.wrap_t().into(),
+        }
+    }
+}
+
+// Matches what `SamplerData::new` gives for gltf's default sampler
+impl Default for SamplerData {
+    fn default() -> Self {
+        Self {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: Some(Filter::Linear),
+            wrap_s: WrapMode::Repeat,
+            wrap_t: WrapMode::Repeat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureData {
+    pub image_index: usize,
+    pub sampler: SamplerData,
+}
+
+// Channels of 16-bit and float formats are stored in native endianness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Rgba8,
+    Rgba16,
+    Rgba32F,
+}
+
+impl ImageFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba8 => 4,
+            Self::Rgba16 => 8,
+            Self::Rgba32F => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ImageData {
     pub width: u32,
     pub height: u32,
+    pub format: ImageFormat,
     pub bytes: Vec<u8>,
+    // Levels below the base one, empty unless mips were generated
+    pub mips: Vec<Vec<u8>>,
+}
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+impl ImageData {
+    pub(super) fn white() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            format: ImageFormat::Rgba8,
+            bytes: vec![255, 255, 255, 255],
+            mips: Vec::new(),
+        }
+    }
+
+    // Loads the base level and every pre-built mip level of a KTX2 texture.
+    // TODO: Transcode Basis Universal and zstd supercompressed textures
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self, GltfSceneError> {
+        let reader = ktx2::Reader::new(bytes)?;
+        let header = reader.header();
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(GltfSceneError::UnsupportedKtx2(format!(
+                "{:?} supercompression",
+                scheme
+            )));
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            return Err(GltfSceneError::UnsupportedKtx2(
+                "array, cube map and 3D textures".to_owned(),
+            ));
+        }
+        // KTX2 data is little endian, same as every platform we run on
+        let format = match header.format {
+            Some(ktx2::Format::R8G8B8A8_UNORM) | Some(ktx2::Format::R8G8B8A8_SRGB) => {
+                ImageFormat::Rgba8
+            }
+            Some(ktx2::Format::R16G16B16A16_UNORM) => ImageFormat::Rgba16,
+            Some(ktx2::Format::R32G32B32A32_SFLOAT) => ImageFormat::Rgba32F,
+            format => {
+                return Err(GltfSceneError::UnsupportedKtx2(format!(
+                    "{:?} format",
+                    format
+                )))
+            }
+        };
+
+        let mut image = Self {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            format,
+            bytes: Vec::new(),
+            mips: Vec::new(),
+        };
+        for (level, data) in reader.levels().enumerate() {
+            let (width, height) = image.mip_size(level as u32);
+            let expected = (width * height) as usize * format.bytes_per_pixel();
+            if data.len() < expected {
+                return Err(GltfSceneError::ImageLength {
+                    expected,
+                    actual: data.len(),
+                });
+            }
+            if level == 0 {
+                image.bytes = data[..expected].to_vec();
+            } else {
+                image.mips.push(data[..expected].to_vec());
+            }
+        }
+        Ok(image)
+    }
+
+    // Includes the base level, levels halve down to 1x1
+    pub fn num_mip_levels(&self) -> u32 {
+        32 - self.width.max(self.height).max(1).leading_zeros()
+    }
+
+    pub fn mip_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    pub fn generate_mips(&mut self, filter: MipFilter, color_space: ColorSpace) {
+        self.mips = build_mip_chain(self, filter, color_space);
+    }
+
+    // Float channels are clamped to [0, 1]
+    pub fn to_rgba8(&self) -> ImageData {
+        let convert = |bytes: &[u8]| -> Vec<u8> {
+            match self.format {
+                ImageFormat::Rgba8 => bytes.to_vec(),
+                ImageFormat::Rgba16 => bytes
+                    .chunks_exact(2)
+                    .map(|channel| {
+                        let value = u16::from_ne_bytes([channel[0], channel[1]]);
+                        ((value as u32 * 255 + 32767) / 65535) as u8
+                    })
+                    .collect(),
+                ImageFormat::Rgba32F => bytes
+                    .chunks_exact(4)
+                    .map(|channel| {
+                        let value =
+                            f32::from_ne_bytes([channel[0], channel[1], channel[2], channel[3]]);
+                        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                    })
+                    .collect(),
+            }
+        };
+        ImageData {
+            width: self.width,
+            height: self.height,
+            format: ImageFormat::Rgba8,
+            bytes: convert(&self.bytes),
+            mips: self.mips.iter().map(|mip| convert(mip)).collect(),
+        }
+    }
 }
 
 pub struct GltfScene {
     gltf: gltf::Document,
     buffers: Vec<gltf::buffer::Data>,
     images: Vec<ImageData>,
+    textures: Vec<TextureData>,
+    // Json of every material, for the extensions gltf doesn't parse
+    materials_json: Vec<serde_json::Value>,
+    // Buffers and images read from disk, which the scene cache checks along with the file
+    external_files: Vec<PathBuf>,
+    // Textures whose KHR_texture_basisu image couldn't be loaded and use their source instead
+    basisu_fallbacks: Vec<usize>,
+    normal_generation: NormalGeneration,
 }
 
 impl GltfScene {
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
-        let (document, buffers, images) = gltf::import(path)?;
-        let mut images = images
-            .into_iter()
-            .map(|image_data| ImageData {
-                width: image_data.width,
-                height: image_data.height,
-                bytes: build_rgba_buffer(image_data),
+        let base = path.parent().unwrap_or_else(|| Path::new("./"));
+        Ok(Self::from_gltf(&std::fs::read(path)?, Some(base))?)
+    }
+
+    // Compiles the default scene, reusing `<path>.cache` when it is up to date. The error of a
+    // failed cache write is returned with the scene.
+    pub fn load_cached(path: impl AsRef<Path>) -> Result<(CachedScene, Option<anyhow::Error>)> {
+        cache::load_or_compile(path.as_ref(), |path, source| {
+            let base = path.parent().unwrap_or_else(|| Path::new("./"));
+            let scene = Self::from_gltf(source, Some(base))?;
+            let cached_scene = CachedScene {
+                compiled_scene: scene.compile()?,
+                images: scene.images,
+                textures: scene.textures,
+            };
+            Ok((cached_scene, scene.external_files))
+        })
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        Ok(Self::from_gltf(slice, None)?)
+    }
+
+    pub(super) fn from_gltf(slice: &[u8], base: Option<&Path>) -> Result<Self, GltfSceneError> {
+        let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(slice)?;
+        let buffers = import_buffers(&document, base, blob)?;
+        let external_files = document
+            .buffers()
+            .filter_map(|buffer| match buffer.source() {
+                gltf::buffer::Source::Uri(uri) => Some(uri),
+                gltf::buffer::Source::Bin => None,
             })
-            .collect::<Vec<_>>();
+            .chain(document.images().filter_map(|image| match image.source() {
+                gltf::image::Source::Uri { uri, .. } => Some(uri),
+                gltf::image::Source::View { .. } => None,
+            }))
+            .filter_map(|uri| external_path(base, uri))
+            .collect();
+
+        // KTX2 images referenced through KHR_texture_basisu replace the texture's source,
+        // unless they use a format we can't load yet
+        let json = raw_json(slice)?;
+        let basisu_sources = basisu_sources(&json, document.images().len());
+        let gltf_images = document.images().collect::<Vec<_>>();
+        let mut basisu_images = gltf_images.iter().map(|_| None).collect::<Vec<_>>();
+        let mut unsupported = vec![false; gltf_images.len()];
+        let mut image_indices = Vec::new();
+        let mut basisu_fallbacks = Vec::new();
+        for texture in document.textures() {
+            let mut image_index = texture.source().index();
+            if let Some(basisu) = basisu_sources[texture.index()] {
+                if basisu_images[basisu].is_none() && !unsupported[basisu] {
+                    match import_image(&gltf_images[basisu], base, &buffers) {
+                        Ok(image) => basisu_images[basisu] = Some(image),
+                        Err(GltfSceneError::UnsupportedKtx2(_)) => unsupported[basisu] = true,
+                        Err(err) => return Err(err),
+                    }
+                }
+                if basisu_images[basisu].is_some() {
+                    image_index = basisu;
+                } else {
+                    basisu_fallbacks.push(texture.index());
+                }
+            }
+            image_indices.push(image_index);
+        }
+
+        // Fallback images of loaded KTX2 images, and KTX2 images nothing ended up using,
+        // are never decoded
+        let mut skip_images = vec![false; gltf_images.len()];
+        for texture in document.textures() {
+            if let Some(basisu) = basisu_sources[texture.index()] {
+                skip_images[texture.source().index()] = true;
+                skip_images[basisu] = true;
+            }
+        }
+        for image_index in &image_indices {
+            skip_images[*image_index] = false;
+        }
+
+        let mut images = gltf_images
+            .iter()
+            .zip(basisu_images)
+            .map(|(image, basisu_image)| match basisu_image {
+                Some(basisu_image) => Ok(basisu_image),
+                None if skip_images[image.index()] => Ok(ImageData::white()),
+                None => import_image(image, base, &buffers),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         // Add 1x1 white texture to the back
-        images.push(ImageData {
-            width: 1,
-            height: 1,
-            bytes: build_rgba_buffer(gltf::image::Data {
-                pixels: vec![255, 255, 255, 255],
-                format: gltf::image::Format::R8G8B8A8,
-                width: 1,
-                height: 1,
-            }),
-        });
+        images.push(ImageData::white());
+        let textures = document
+            .textures()
+            .map(|texture| TextureData {
+                image_index: image_indices[texture.index()],
+                sampler: SamplerData::new(&texture.sampler()),
+            })
+            .collect::<Vec<_>>();
         Ok(Self {
             gltf: document,
             buffers,
             images,
+            textures,
+            materials_json: json["materials"].as_array().cloned().unwrap_or_default(),
+            external_files,
+            basisu_fallbacks,
+            normal_generation: NormalGeneration::default(),
         })
     }
 
-    pub fn compile(&self) -> Result<CompiledScene, GltfSceneError> {
-        let buffers = self.buffer_data();
+    pub fn with_normal_generation(mut self, normal_generation: NormalGeneration) -> Self {
+        self.normal_generation = normal_generation;
+        self
+    }
 
-        // TODO: Have a way to compile other scenes
-        if let Some(scene) = self
-            .gltf
-            .default_scene()
-            .or_else(|| self.gltf.scenes().next())
+    // Images used as base colour or emissive textures are filtered as sRGB
+    pub fn with_mipmaps(mut self, filter: MipFilter) -> Self {
+        let mut color_images = vec![false; self.images.len()];
+        for material in self.gltf.materials() {
+            let base_color = material.pbr_metallic_roughness().base_color_texture();
+            let emissive = material.emissive_texture();
+            for info in base_color.into_iter().chain(emissive) {
+                color_images[self.textures[info.texture().index()].image_index] = true;
+            }
+        }
+        // Keep the mip levels that came with the image
+        for (image, is_color) in self
+            .images
+            .iter_mut()
+            .zip(color_images)
+            .filter(|(image, _)| image.mips.is_empty())
         {
-            let mut compiled_scene = CompiledScene::default();
-
-            let mut process_node = |node: &gltf::scene::Node, transform: Mat4| {
-                // Process Mesh
-                if let Some(mesh) = node.mesh() {
-                    // Process Mesh primitives
-                    for prim in mesh.primitives() {
-                        let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
-
-                        let material_idx = compiled_scene.materials.len() as u32;
-
-                        let material = Material::new(&prim.material());
-                        compiled_scene.materials.push(material);
-
-                        // Process vertex positions
-                        let mut positions = if let Some(iter) = reader.read_positions() {
-                            iter.map(|data| data.into()).collect::<Vec<Vec4>>()
-                        } else {
-                            return;
-                        };
-
-                        // Process Normals
-                        let mut normals = if let Some(iter) = reader.read_normals() {
-                            iter.map(|data| data.into()).collect::<Vec<Vec3>>()
-                        } else {
-                            (0..positions.len())
-                                .map(|_| Vec3::new(0.0, 0.0, 0.0))
-                                .collect::<Vec<Vec3>>()
-                        };
-
-                        // Process colors
-                        let mut colors = if let Some(iter) = reader.read_colors(0) {
-                            iter.into_rgba_f32()
-                                .map(|data| data.into())
-                                .collect::<Vec<Vec4>>()
-                        } else {
-                            vec![Vec4::new(1.0, 1.0, 1.0, 1.0); positions.len()]
-                        };
-
-                        // Process uvs
-                        let mut uvs = if let Some(iter) = reader.read_tex_coords(0) {
-                            iter.into_f32()
-                                .map(|data| data.into())
-                                .collect::<Vec<Vec2>>()
-                        } else {
-                            vec![Vec2::new(0.0, 0.0); positions.len()]
-                        };
-
-                        // Process Mesh indices
-                        let mut indices = {
-                            let mut indices = if let Some(indices_reader) = reader.read_indices() {
-                                indices_reader.into_u32().collect::<Vec<u32>>()
-                            } else {
-                                (0..positions.len() as u32).collect::<Vec<u32>>()
-                            };
-
-                            let base_index = compiled_scene.positions.len() as u32;
-                            for i in &mut indices {
-                                *i += base_index;
-                            }
-
-                            indices
-                        };
-
-                        let mesh_draw = MeshDraw {
-                            start_idx: compiled_scene.indices.len() as u32,
-                            num_indices: indices.len() as u32,
-                            material_idx,
-                            transform_matrix: transform,
-                        };
-
-                        // TODO: remove need for mut bindings
-                        compiled_scene.positions.append(&mut positions);
-                        compiled_scene.colors.append(&mut colors);
-                        compiled_scene.normals.append(&mut normals);
-                        compiled_scene.uvs.append(&mut uvs);
-                        compiled_scene.indices.append(&mut indices);
-                        compiled_scene.mesh_draws.push(mesh_draw);
+            let color_space = if is_color {
+                ColorSpace::Srgb
+            } else {
+                ColorSpace::Linear
+            };
+            image.generate_mips(filter, color_space);
+        }
+        self
+    }
+
+    pub fn scenes(&self) -> Vec<SceneInfo> {
+        let default_index = self.gltf.default_scene().map(|scene| scene.index());
+        self.gltf
+            .scenes()
+            .map(|scene| SceneInfo {
+                index: scene.index(),
+                name: scene.name().map(str::to_owned),
+                is_default: Some(scene.index()) == default_index,
+            })
+            .collect()
+    }
+
+    // Vertex formats of every primitive in the document, whether any scene uses it or not
+    pub fn report(&self) -> Vec<PrimitiveReport> {
+        let mut reports = Vec::new();
+        for mesh in self.gltf.meshes() {
+            for prim in mesh.primitives() {
+                let mut attributes = prim
+                    .attributes()
+                    .map(|(semantic, accessor)| AttributeFormat {
+                        ignored: !is_compiled_attribute(&prim, &semantic),
+                        semantic: semantic.to_string(),
+                        morph_target: None,
+                        format: AccessorFormat::new(&accessor),
+                    })
+                    .collect::<Vec<_>>();
+                // Attributes are stored in a map, sort them so reports are stable
+                attributes.sort_by(|a, b| a.semantic.cmp(&b.semantic));
+
+                for (idx, target) in prim.morph_targets().enumerate() {
+                    let target_attributes = [
+                        ("POSITION", target.positions(), false),
+                        ("NORMAL", target.normals(), false),
+                        ("TANGENT", target.tangents(), true),
+                    ];
+                    for (semantic, accessor, ignored) in target_attributes {
+                        if let Some(accessor) = accessor {
+                            attributes.push(AttributeFormat {
+                                semantic: semantic.to_owned(),
+                                morph_target: Some(idx),
+                                format: AccessorFormat::new(&accessor),
+                                ignored,
+                            });
+                        }
                     }
                 }
-            };
 
-            for node in scene.nodes() {
-                compile_gltf_node(&node, &mut process_node, Mat4::identity());
+                reports.push(PrimitiveReport {
+                    mesh_index: mesh.index(),
+                    mesh_name: mesh.name().map(str::to_owned),
+                    primitive_index: prim.index(),
+                    indices: prim
+                        .indices()
+                        .map(|accessor| AccessorFormat::new(&accessor)),
+                    attributes,
+                });
             }
+        }
+        reports
+    }
 
-            for camera in self.gltf.cameras() {
-                let camera_type = match camera.projection() {
-                    gltf::camera::Projection::Orthographic(ortho) => {
-                        CameraType::Orthographic(OrtographicData {
-                            left: 0.0,
-                            right: ortho.xmag(),
-                            top: ortho.ymag(),
-                            bottom: 0.0,
-                            near: ortho.znear(),
-                            far: ortho.zfar(),
-                        })
-                    }
-                    gltf::camera::Projection::Perspective(perspective) => {
-                        CameraType::Perspective(PerspectiveData {
-                            aspect_ratio: perspective.aspect_ratio(),
-                            y_fov: perspective.yfov(),
-                            z_far: perspective.zfar(),
-                            z_near: perspective.znear(),
-                        })
-                    }
-                };
-                compiled_scene.cameras.push(Camera::from_type(camera_type));
+    pub fn compile(&self) -> Result<CompiledScene, GltfSceneError> {
+        let scene = self
+            .gltf
+            .default_scene()
+            .or_else(|| self.gltf.scenes().next())
+            .ok_or(GltfSceneError::NoDefaultScene)?;
+        self.compile_gltf_scene(&scene)
+    }
+
+    pub fn compile_scene(&self, selector: SceneSelector) -> Result<CompiledScene, GltfSceneError> {
+        let scene = match selector {
+            SceneSelector::Index(index) => self
+                .gltf
+                .scenes()
+                .nth(index)
+                .ok_or(GltfSceneError::SceneIndexNotFound(index))?,
+            SceneSelector::Name(name) => self
+                .gltf
+                .scenes()
+                .find(|scene| scene.name() == Some(name.as_str()))
+                .ok_or(GltfSceneError::SceneNameNotFound(name))?,
+        };
+        self.compile_gltf_scene(&scene)
+    }
+
+    fn compile_gltf_scene(&self, scene: &gltf::Scene) -> Result<CompiledScene, GltfSceneError> {
+        let buffers = self.buffer_data();
+
+        let mut compiled_scene = CompiledScene {
+            materials: self
+                .gltf
+                .materials()
+                .map(|material| {
+                    let json = material
+                        .index()
+                        .and_then(|idx| self.materials_json.get(idx))
+                        .unwrap_or(&serde_json::Value::Null);
+                    Material::new(&material, json)
+                })
+                .chain(std::iter::once(Material::default()))
+                .collect(),
+            skins: self
+                .gltf
+                .skins()
+                .map(|skin| Skin::new(&skin, buffers))
+                .collect(),
+            animations: self
+                .gltf
+                .animations()
+                .map(|animation| Animation::new(&animation, buffers))
+                .collect(),
+            ..Default::default()
+        };
+        let mut nodes = self
+            .gltf
+            .nodes()
+            .map(|node| SceneNode {
+                name: node.name().map(str::to_owned),
+                children: node.children().map(|child| child.index()).collect(),
+                transform: node.transform().into(),
+                morph_weights: node.weights().map(<[f32]>::to_vec).unwrap_or_default(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        // Primitives are compiled once and shared by every node that instances their mesh
+        let mut compiled_primitives = HashMap::<(usize, usize), Option<u32>>::new();
+
+        let mut process_node = |node: &gltf::scene::Node, transform: Mat4| {
+            let scene_node = &mut nodes[node.index()];
+
+            // Process Camera
+            if let Some(camera) = node.camera() {
+                let name = camera.name().or_else(|| node.name()).map(str::to_owned);
+                scene_node.camera = Some(compiled_scene.cameras.len());
+                compiled_scene.cameras.push(
+                    Camera::from_type_and_transform(camera_type(&camera), transform)
+                        .with_name(name),
+                );
             }
-            if compiled_scene.cameras.is_empty() {
-                compiled_scene
-                    .cameras
-                    .push(Camera::from_type(CameraType::perspective(
-                        Default::default(),
-                    )));
+
+            // Process Light
+            if let Some(light) = node.light() {
+                scene_node.light = Some(compiled_scene.lights.len());
+                compiled_scene.lights.push(Light::new(&light, transform));
             }
-            Ok(compiled_scene)
-        } else {
-            Err(GltfSceneError::NoDefaultScene)
+
+            // Process Mesh
+            if let Some(mesh) = node.mesh() {
+                // Process Mesh primitives
+                for prim in mesh.primitives() {
+                    let primitive_idx = *compiled_primitives
+                        .entry((mesh.index(), prim.index()))
+                        .or_insert_with(|| {
+                            self.compile_primitive(&prim, mesh.weights(), &mut compiled_scene)
+                        });
+
+                    if let Some(primitive_idx) = primitive_idx {
+                        let primitive = &compiled_scene.primitives[primitive_idx as usize];
+                        scene_node.mesh_draws.push(compiled_scene.mesh_draws.len());
+                        compiled_scene.mesh_draws.push(MeshDraw {
+                            primitive_idx,
+                            material_idx: primitive.material_idx,
+                            topology: primitive.topology,
+                            node_idx: node.index() as u32,
+                            skin_idx: node.skin().map(|skin| skin.index() as u32),
+                        });
+                    }
+                }
+            }
+        };
+
+        for node in scene.nodes() {
+            compile_gltf_node(&node, &mut process_node, Mat4::identity());
+        }
+        let roots = scene.nodes().map(|node| node.index()).collect();
+        compiled_scene.graph = SceneGraph::with_roots(nodes, roots);
+
+        if compiled_scene.cameras.is_empty() {
+            compiled_scene
+                .cameras
+                .push(Camera::from_type(CameraType::perspective(
+                    Default::default(),
+                )));
+        }
+        Ok(compiled_scene)
+    }
+
+    fn compile_primitive(
+        &self,
+        prim: &gltf::Primitive,
+        morph_weights: Option<&[f32]>,
+        compiled_scene: &mut CompiledScene,
+    ) -> Option<u32> {
+        use gltf::Semantic;
+
+        let buffers = self.buffer_data();
+        let read = |semantic: Semantic| {
+            prim.get(&semantic)
+                .and_then(|accessor| read_floats(&accessor, buffers))
+        };
+
+        let material_idx = match prim.material().index() {
+            Some(idx) => idx as u32,
+            // The default material is always stored after the document's materials
+            None => compiled_scene.materials.len() as u32 - 1,
+        };
+        // Process vertex positions
+        let positions = read(Semantic::Positions)?
+            .into_iter()
+            .map(|[x, y, z, _]| Vec4::new(x, y, z, 1.0))
+            .collect::<Vec<_>>();
+
+        // Process every color and uv set, set 0 is white or zeroed when the primitive has none
+        let mut color_sets = (0..)
+            .map_while(|set| {
+                let has_alpha = prim.get(&Semantic::Colors(set))?.dimensions()
+                    == gltf::accessor::Dimensions::Vec4;
+                read(Semantic::Colors(set)).map(|data| {
+                    data.into_iter()
+                        .map(|[r, g, b, a]| Vec4::new(r, g, b, if has_alpha { a } else { 1.0 }))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        if color_sets.is_empty() {
+            color_sets.push(vec![Vec4::new(1.0, 1.0, 1.0, 1.0); positions.len()]);
+        }
+        let mut uv_sets = (0..)
+            .map_while(|set| {
+                read(Semantic::TexCoords(set)).map(|data| {
+                    data.into_iter()
+                        .map(|[u, v, _, _]| Vec2::new(u, v))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        if uv_sets.is_empty() {
+            uv_sets.push(vec![Vec2::new(0.0, 0.0); positions.len()]);
         }
+
+        // Process skinning attributes
+        let joints = if let Some(data) = prim
+            .get(&Semantic::Joints(0))
+            .and_then(|accessor| read_integers(&accessor, buffers))
+        {
+            data.into_iter()
+                .map(|joints| joints.map(|joint| joint as u16))
+                .collect::<Vec<[u16; 4]>>()
+        } else {
+            vec![[0; 4]; positions.len()]
+        };
+        let weights = if let Some(data) = read(Semantic::Weights(0)) {
+            data.into_iter()
+                .map(|data| data.into())
+                .collect::<Vec<Vec4>>()
+        } else {
+            vec![Vec4::new(0.0, 0.0, 0.0, 0.0); positions.len()]
+        };
+
+        // Process tangents
+        let tangents = read(Semantic::Tangents).map(|data| {
+            data.into_iter()
+                .map(|data| data.into())
+                .collect::<Vec<Vec4>>()
+        });
+
+        // Process Mesh indices
+        let indices = if let Some(data) = prim
+            .indices()
+            .and_then(|accessor| read_integers(&accessor, buffers))
+        {
+            data.into_iter()
+                .map(|[idx, _, _, _]| idx)
+                .collect::<Vec<u32>>()
+        } else {
+            (0..positions.len() as u32).collect::<Vec<u32>>()
+        };
+        let (topology, indices) = list_topology(prim.mode(), indices);
+
+        // Process morph targets
+        let morph_targets = MorphTargets::new(prim, buffers, morph_weights);
+
+        // Process Normals, missing ones are generated when the primitive is pushed
+        let normals = read(Semantic::Normals).map(|data| {
+            data.into_iter()
+                .map(|[x, y, z, _]| Vec3::new(x, y, z))
+                .collect::<Vec<_>>()
+        });
+        let primitive = PrimitiveData {
+            positions,
+            color_sets,
+            uv_sets,
+            normals,
+            tangents,
+            joints,
+            weights,
+            morph_targets,
+            indices,
+            topology,
+        };
+        Some(primitive.push(material_idx, self.normal_generation, compiled_scene))
     }
 
     pub fn buffer_data(&self) -> &[gltf::buffer::Data] {
@@ -216,48 +815,574 @@ impl GltfScene {
     pub fn image_data(&self) -> &[ImageData] {
         &self.images
     }
+
+    pub fn texture_data(&self) -> &[TextureData] {
+        &self.textures
+    }
+
+    pub fn external_files(&self) -> &[PathBuf] {
+        &self.external_files
+    }
+
+    pub fn basisu_fallbacks(&self) -> &[usize] {
+        &self.basisu_fallbacks
+    }
+}
+
+// `compile` reads color and uv sets until the first missing one, and a single set of joints
+// and weights
+fn is_compiled_attribute(prim: &gltf::Primitive, semantic: &gltf::Semantic) -> bool {
+    use gltf::Semantic;
+
+    match semantic {
+        Semantic::Positions | Semantic::Normals | Semantic::Tangents => true,
+        Semantic::Colors(set) => (0..*set).all(|set| prim.get(&Semantic::Colors(set)).is_some()),
+        Semantic::TexCoords(set) => {
+            (0..*set).all(|set| prim.get(&Semantic::TexCoords(set)).is_some())
+        }
+        Semantic::Joints(set) | Semantic::Weights(set) => *set == 0,
+        Semantic::Extras(_) => false,
+    }
+}
+
+fn list_topology(mode: gltf::mesh::Mode, indices: Vec<u32>) -> (Topology, Vec<u32>) {
+    use gltf::mesh::Mode;
+
+    match mode {
+        Mode::Points => (Topology::Points, indices),
+        Mode::Lines => (Topology::Lines, indices),
+        Mode::LineStrip => (Topology::Lines, line_strip_to_list(&indices)),
+        Mode::LineLoop => (Topology::Lines, line_loop_to_list(&indices)),
+        Mode::Triangles => (Topology::Triangles, indices),
+        Mode::TriangleStrip => (Topology::Triangles, triangle_strip_to_list(&indices)),
+        Mode::TriangleFan => (Topology::Triangles, triangle_fan_to_list(&indices)),
+    }
+}
+
+fn camera_type(camera: &gltf::Camera) -> CameraType {
+    match camera.projection() {
+        gltf::camera::Projection::Orthographic(ortho) => {
+            CameraType::Orthographic(OrtographicData {
+                x_mag: ortho.xmag(),
+                y_mag: ortho.ymag(),
+                near: ortho.znear(),
+                far: ortho.zfar(),
+                fit: OrthographicFit::Both,
+            })
+        }
+        gltf::camera::Projection::Perspective(perspective) => {
+            CameraType::Perspective(PerspectiveData {
+                aspect_ratio: perspective.aspect_ratio(),
+                y_fov: perspective.yfov(),
+                z_far: perspective.zfar(),
+                z_near: perspective.znear(),
+            })
+        }
+    }
+}
+
+fn read_uri(base: Option<&Path>, uri: &str) -> Result<Vec<u8>, GltfSceneError> {
+    if let Some(rest) = uri.strip_prefix("data:") {
+        match rest.split_once(";base64,") {
+            Some((_mime_type, data)) => Ok(base64::decode(data)?),
+            None => Err(GltfSceneError::UnsupportedScheme(uri.to_owned())),
+        }
+    } else if let Some(path) = external_path(base, uri) {
+        Ok(std::fs::read(path)?)
+    } else if uri.contains(':') {
+        Err(GltfSceneError::UnsupportedScheme(uri.to_owned()))
+    } else {
+        Err(GltfSceneError::ExternalReference(uri.to_owned()))
+    }
+}
+
+// `None` for data uris, other schemes and relative paths without a base
+fn external_path(base: Option<&Path>, uri: &str) -> Option<PathBuf> {
+    if let Some(path) = uri
+        .strip_prefix("file://")
+        .or_else(|| uri.strip_prefix("file:"))
+    {
+        Some(PathBuf::from(path))
+    } else if uri.contains(':') {
+        None
+    } else {
+        base.map(|base| base.join(uri))
+    }
+}
+
+fn import_buffers(
+    document: &gltf::Document,
+    base: Option<&Path>,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<gltf::buffer::Data>, GltfSceneError> {
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let mut data = match buffer.source() {
+            gltf::buffer::Source::Bin => blob.take().ok_or(GltfSceneError::MissingBlob)?,
+            gltf::buffer::Source::Uri(uri) => read_uri(base, uri)?,
+        };
+        if data.len() < buffer.length() {
+            return Err(GltfSceneError::BufferLength {
+                index: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len(),
+            });
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        buffers.push(gltf::buffer::Data(data));
+    }
+    Ok(buffers)
+}
+
+// Parses the json chunk again, for extensions gltf doesn't expose
+fn raw_json(slice: &[u8]) -> Result<serde_json::Value, GltfSceneError> {
+    if slice.starts_with(b"glTF") {
+        let glb = gltf::binary::Glb::from_slice(slice)?;
+        Ok(serde_json::from_slice(&glb.json)?)
+    } else {
+        Ok(serde_json::from_slice(slice)?)
+    }
+}
+
+fn basisu_sources(json: &serde_json::Value, num_images: usize) -> Vec<Option<usize>> {
+    json["textures"]
+        .as_array()
+        .map(|textures| {
+            textures
+                .iter()
+                .map(|texture| {
+                    texture["extensions"]["KHR_texture_basisu"]["source"]
+                        .as_u64()
+                        .map(|source| source as usize)
+                        .filter(|source| *source < num_images)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
-fn build_rgba_buffer(image: gltf::image::Data) -> Vec<u8> {
-    let mut buffer = Vec::new();
-    let size = image.width * image.height;
-    for index in 0..size {
-        let rgba = get_next_rgba(&image.pixels, image.format, index as usize);
-        buffer.extend_from_slice(&rgba);
-    }
-    buffer
-}
-
-fn get_next_rgba(pixels: &[u8], format: gltf::image::Format, index: usize) -> [u8; 4] {
-    match format {
-        gltf::image::Format::R8 => [pixels[index], 0, 0, std::u8::MAX],
-        gltf::image::Format::R8G8 => [pixels[index * 2], pixels[index * 2 + 1], 0, std::u8::MAX],
-        gltf::image::Format::R8G8B8 => [
-            pixels[index * 3],
-            pixels[index * 3 + 1],
-            pixels[index * 3 + 2],
-            std::u8::MAX,
-        ],
-        gltf::image::Format::B8G8R8 => [
-            pixels[index * 3 + 2],
-            pixels[index * 3 + 1],
-            pixels[index * 3],
-            std::u8::MAX,
-        ],
-        gltf::image::Format::R8G8B8A8 => [
-            pixels[index * 4],
-            pixels[index * 4 + 1],
-            pixels[index * 4 + 2],
-            pixels[index * 4 + 3],
-        ],
-        gltf::image::Format::B8G8R8A8 => [
-            pixels[index * 4 + 2],
-            pixels[index * 4 + 1],
-            pixels[index * 4],
-            pixels[index * 4 + 3],
-        ],
-        _ => {
-            panic!("image format not supported")
+fn import_image(
+    image: &gltf::Image,
+    base: Option<&Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Result<ImageData, GltfSceneError> {
+    let encoded_image = match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let begin = view.offset();
+            let end = begin + view.length();
+            buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(begin..end))
+                .ok_or(GltfSceneError::BufferViewRange(view.index()))?
+                .to_vec()
         }
+        gltf::image::Source::Uri { uri, .. } => read_uri(base, uri)?,
+    };
+    decode_image(&encoded_image)
+}
+
+pub(super) fn decode_image(encoded_image: &[u8]) -> Result<ImageData, GltfSceneError> {
+    use gltf::image::Format;
+    use image::DynamicImage;
+
+    if encoded_image.starts_with(&KTX2_MAGIC) {
+        return ImageData::from_ktx2(encoded_image);
+    }
+
+    let image = image::load_from_memory(encoded_image)?;
+    let (width, height) = (image.width(), image.height());
+    let (format, pixels) = match image {
+        DynamicImage::ImageLuma8(_) => (Format::R8, image.as_bytes().to_vec()),
+        DynamicImage::ImageLumaA8(_) => (Format::R8G8, image.as_bytes().to_vec()),
+        DynamicImage::ImageRgb8(_) => (Format::R8G8B8, image.as_bytes().to_vec()),
+        DynamicImage::ImageRgba8(_) => (Format::R8G8B8A8, image.as_bytes().to_vec()),
+        DynamicImage::ImageLuma16(_) => (Format::R16, image.as_bytes().to_vec()),
+        DynamicImage::ImageLumaA16(_) => (Format::R16G16, image.as_bytes().to_vec()),
+        DynamicImage::ImageRgb16(_) => (Format::R16G16B16, image.as_bytes().to_vec()),
+        DynamicImage::ImageRgba16(_) => (Format::R16G16B16A16, image.as_bytes().to_vec()),
+        // gltf has no float formats, so these skip build_rgba_buffer
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            return Ok(ImageData {
+                width,
+                height,
+                format: ImageFormat::Rgba32F,
+                bytes: image
+                    .into_rgba32f()
+                    .into_raw()
+                    .into_iter()
+                    .flat_map(f32::to_ne_bytes)
+                    .collect(),
+                mips: Vec::new(),
+            });
+        }
+        _ => return Err(GltfSceneError::UnsupportedImageFormat(image.color())),
+    };
+    build_rgba_buffer(gltf::image::Data {
+        pixels,
+        format,
+        width,
+        height,
+    })
+}
+
+// Expands every format to four channels, keeping the precision of 16-bit formats
+fn build_rgba_buffer(image: gltf::image::Data) -> Result<ImageData, GltfSceneError> {
+    use gltf::image::Format;
+
+    let (num_channels, channel_size, bgr) = match image.format {
+        Format::R8 => (1, 1, false),
+        Format::R8G8 => (2, 1, false),
+        Format::R8G8B8 => (3, 1, false),
+        Format::R8G8B8A8 => (4, 1, false),
+        Format::B8G8R8 => (3, 1, true),
+        Format::B8G8R8A8 => (4, 1, true),
+        Format::R16 => (1, 2, false),
+        Format::R16G16 => (2, 2, false),
+        Format::R16G16B16 => (3, 2, false),
+        Format::R16G16B16A16 => (4, 2, false),
+    };
+    let format = if channel_size == 2 {
+        ImageFormat::Rgba16
+    } else {
+        ImageFormat::Rgba8
+    };
+
+    let size = (image.width * image.height) as usize;
+    let pixel_size = num_channels * channel_size;
+    if image.pixels.len() < size * pixel_size {
+        return Err(GltfSceneError::ImageLength {
+            expected: size * pixel_size,
+            actual: image.pixels.len(),
+        });
+    }
+
+    let mut buffer = Vec::with_capacity(size * format.bytes_per_pixel());
+    for pixel in image.pixels.chunks_exact(pixel_size).take(size) {
+        for channel in 0..4 {
+            let source = if bgr && channel < 3 {
+                2 - channel
+            } else {
+                channel
+            };
+            if source < num_channels {
+                let begin = source * channel_size;
+                buffer.extend_from_slice(&pixel[begin..begin + channel_size]);
+            } else if channel == 3 {
+                // Missing alpha is opaque, an all ones channel regardless of endianness
+                buffer.resize(buffer.len() + channel_size, u8::MAX);
+            } else {
+                buffer.resize(buffer.len() + channel_size, 0);
+            }
+        }
+    }
+    Ok(ImageData {
+        width: image.width,
+        height: image.height,
+        format,
+        bytes: buffer,
+        mips: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{
+        accessor::ComponentType,
+        fixtures::{self, Document},
+    };
+    use serde_json::json;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    // Same triangle and 2x2 red texture, with the image in the buffer or as a data uri
+    fn textured_triangle(image_in_view: bool) -> Document {
+        let mut document = fixtures::textured_triangle();
+        let png = fixtures::png(2, 2, RED);
+        let image = if image_in_view {
+            let view = document.push_view(&png);
+            json!({ "bufferView": view, "mimeType": "image/png" })
+        } else {
+            json!({ "uri": fixtures::data_uri("image/png", &png) })
+        };
+        document.push("images", image);
+        document.push("textures", json!({ "source": 0 }));
+        document
+    }
+
+    fn check_textured_triangle(scene: &GltfScene) -> CompiledScene {
+        let compiled_scene = scene.compile().unwrap();
+        assert_eq!(compiled_scene.positions.len(), 3);
+        assert_eq!(compiled_scene.indices, [0, 1, 2]);
+        let uv = compiled_scene.uv_sets[0][1];
+        assert_eq!((uv.x(), uv.y()), (1.0, 1.0));
+
+        // The white placeholder is always stored after the document's images
+        let images = scene.image_data();
+        assert_eq!(images.len(), 2);
+        assert_eq!((images[0].width, images[0].height), (2, 2));
+        assert_eq!(images[0].format, ImageFormat::Rgba8);
+        assert_eq!(images[0].bytes, RED.repeat(4));
+        assert_eq!(scene.texture_data()[0].image_index, 0);
+        compiled_scene
+    }
+
+    #[test]
+    fn glb_with_buffer_view_image() {
+        let glb = textured_triangle(true).to_glb();
+        check_textured_triangle(&GltfScene::from_slice(&glb).unwrap());
+    }
+
+    #[test]
+    fn data_uri_buffer_and_image() {
+        let gltf = textured_triangle(false).to_gltf();
+        check_textured_triangle(&GltfScene::from_slice(&gltf).unwrap());
+    }
+
+    #[test]
+    fn data_uri_buffer_with_buffer_view_image() {
+        let gltf = textured_triangle(true).to_gltf();
+        check_textured_triangle(&GltfScene::from_slice(&gltf).unwrap());
+    }
+
+    #[test]
+    fn packaging_does_not_change_compiled_scene() {
+        let glb = GltfScene::from_slice(&textured_triangle(true).to_glb()).unwrap();
+        let gltf = GltfScene::from_slice(&textured_triangle(false).to_gltf()).unwrap();
+        assert_eq!(
+            format!("{:?}", glb.compile().unwrap()),
+            format!("{:?}", gltf.compile().unwrap())
+        );
+    }
+
+    #[test]
+    fn image_view_past_buffer_end_is_an_error() {
+        let mut document = fixtures::textured_triangle();
+        let view = document.push_view(&fixtures::png(1, 1, RED));
+        document.json["bufferViews"][view]["byteLength"] = json!(1 << 20);
+        document.push(
+            "images",
+            json!({ "bufferView": view, "mimeType": "image/png" }),
+        );
+        document.push("textures", json!({ "source": 0 }));
+        let error = GltfScene::from_slice(&document.to_glb()).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<GltfSceneError>(),
+            Some(GltfSceneError::BufferViewRange(index)) if *index == view
+        ));
+    }
+
+    // The red PNG as the texture's source, with a KTX2 image as its basisu source
+    fn basisu_triangle(ktx2: &[u8]) -> Document {
+        let mut document = textured_triangle(true);
+        let view = document.push_view(ktx2);
+        document.push(
+            "images",
+            json!({ "bufferView": view, "mimeType": "image/ktx2" }),
+        );
+        document.json["textures"][0]["extensions"] =
+            json!({ "KHR_texture_basisu": { "source": 1 } });
+        document.json["extensionsUsed"] = json!(["KHR_texture_basisu"]);
+        document
+    }
+
+    #[test]
+    fn basisu_texture_falls_back_to_its_source() {
+        // UASTC, which isn't transcoded yet
+        let glb = basisu_triangle(&fixtures::ktx2(0, 4, 4, &[0; 16])).to_glb();
+        let scene = GltfScene::from_slice(&glb).unwrap();
+        assert_eq!(scene.texture_data()[0].image_index, 0);
+        assert_eq!(scene.image_data()[0].bytes, RED.repeat(4));
+        assert_eq!(scene.basisu_fallbacks(), [0]);
+    }
+
+    #[test]
+    fn basisu_texture_replaces_its_source() {
+        const R8G8B8A8_UNORM: u32 = 37;
+        let green = [0, 255, 0, 255];
+        let glb = basisu_triangle(&fixtures::ktx2(R8G8B8A8_UNORM, 2, 2, &green.repeat(4))).to_glb();
+        let scene = GltfScene::from_slice(&glb).unwrap();
+        assert_eq!(scene.texture_data()[0].image_index, 1);
+        assert_eq!(scene.image_data()[1].bytes, green.repeat(4));
+        // The fallback is never decoded
+        assert_eq!(scene.image_data()[0].bytes, [255; 4]);
+        assert!(scene.basisu_fallbacks().is_empty());
+    }
+
+    #[test]
+    fn quantized_positions_report() {
+        const SHORT: u32 = 5122;
+
+        // Normalized shorts padded to 8 bytes per position, as KHR_mesh_quantization requires
+        let mut document = fixtures::textured_triangle();
+        let positions = [[0i16, 0, 0], [i16::MAX, 0, 0], [0, i16::MAX, 0]]
+            .iter()
+            .flat_map(|position| {
+                let [x, y, z] = position.map(i16::to_le_bytes);
+                [x, y, z, [0, 0]].concat()
+            })
+            .collect::<Vec<_>>();
+        let view = document.push_view(&positions);
+        document.json["bufferViews"][view]["byteStride"] = json!(8);
+        let accessor = document.push(
+            "accessors",
+            json!({
+                "bufferView": view,
+                "componentType": SHORT,
+                "normalized": true,
+                "count": 3,
+                "type": "VEC3",
+                "min": [0, 0, 0],
+                "max": [i16::MAX, i16::MAX, 0],
+            }),
+        );
+        document.json["meshes"][0]["primitives"][0]["attributes"]["POSITION"] = json!(accessor);
+        document.json["materials"] = json!([{}]);
+        document.json["extensionsUsed"] = json!(["KHR_mesh_quantization"]);
+        document.json["extensionsRequired"] = json!(["KHR_mesh_quantization"]);
+
+        let scene = GltfScene::from_slice(&document.to_glb()).unwrap();
+        let report = scene.report();
+        let position = report[0]
+            .attributes
+            .iter()
+            .find(|attribute| attribute.semantic == "POSITION")
+            .unwrap();
+        assert_eq!(
+            position.format,
+            AccessorFormat {
+                component_type: ComponentType::I16,
+                normalized: true,
+                sparse: false,
+            }
+        );
+        assert!(!position.ignored);
+
+        let compiled_scene = scene.compile().unwrap();
+        assert_eq!(compiled_scene.positions[1].x(), 1.0);
+        assert_eq!(compiled_scene.positions[2].y(), 1.0);
+    }
+
+    // Sparse data replacing the elements at `indices` with the tightly packed `values`
+    fn sparse(document: &mut Document, indices: &[u16], values: &[f32]) -> serde_json::Value {
+        let index_bytes = indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<_>>();
+        let value_bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        json!({
+            "count": indices.len(),
+            "indices": {
+                "bufferView": document.push_view(&index_bytes),
+                "componentType": fixtures::UNSIGNED_SHORT,
+            },
+            "values": { "bufferView": document.push_view(&value_bytes) },
+        })
+    }
+
+    #[test]
+    fn sparse_positions_replace_their_buffer_view() {
+        let mut document = fixtures::textured_triangle();
+        document.json["materials"] = json!([{}]);
+        document.json["accessors"][0]["sparse"] = sparse(&mut document, &[1], &[2.0, 0.0, 0.0]);
+        document.json["accessors"][0]["max"] = json!([2.0, 1.0, 0.0]);
+
+        let compiled_scene = GltfScene::from_slice(&document.to_glb())
+            .unwrap()
+            .compile()
+            .unwrap();
+        assert_eq!(compiled_scene.positions[1].x(), 2.0);
+        assert_eq!(compiled_scene.positions[2].y(), 1.0);
+    }
+
+    #[test]
+    fn sparse_positions_without_a_buffer_view_start_zeroed() {
+        let mut document = fixtures::textured_triangle();
+        document.json["materials"] = json!([{}]);
+        let sparse = sparse(&mut document, &[1, 2], &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        document.json["accessors"][0] = json!({
+            "componentType": fixtures::FLOAT,
+            "count": 3,
+            "type": "VEC3",
+            "min": [0.0, 0.0, 0.0],
+            "max": [1.0, 1.0, 0.0],
+            "sparse": sparse,
+        });
+
+        let compiled_scene = GltfScene::from_slice(&document.to_glb())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let positions = compiled_scene
+            .positions
+            .iter()
+            .map(|position| [position.x(), position.y(), position.z()])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+    }
+
+    // Skins and animations read their accessors like primitives do
+    #[test]
+    fn sparse_skin_and_animation_accessors() {
+        let mut document = fixtures::textured_triangle();
+        document.json["materials"] = json!([{}]);
+        let mut inverse_bind_matrix = vec![0.0; 16];
+        for idx in [0, 5, 10, 15] {
+            inverse_bind_matrix[idx] = 1.0;
+        }
+        // Column-major, so the translation is in the last column
+        inverse_bind_matrix[12..15].copy_from_slice(&[1.0, 2.0, 3.0]);
+        let sparse_matrices = sparse(&mut document, &[0], &inverse_bind_matrix);
+        let inverse_bind_matrices = document.push(
+            "accessors",
+            json!({
+                "componentType": fixtures::FLOAT,
+                "count": 1,
+                "type": "MAT4",
+                "sparse": sparse_matrices,
+            }),
+        );
+        document.json["nodes"][0]["skin"] = json!(0);
+        document.push("nodes", json!({}));
+        document.json["scenes"][0]["nodes"] = json!([0, 1]);
+        document.push(
+            "skins",
+            json!({ "joints": [1], "inverseBindMatrices": inverse_bind_matrices }),
+        );
+
+        let input = document.push_floats(&[0.0, 1.0], 1);
+        let output = document.push_floats(&[0.0; 6], 3);
+        document.json["accessors"][output]["sparse"] =
+            sparse(&mut document, &[1], &[4.0, 5.0, 6.0]);
+        document.json["accessors"][output]["max"] = json!([4.0, 5.0, 6.0]);
+        document.push(
+            "animations",
+            json!({
+                "channels": [{ "sampler": 0, "target": { "node": 1, "path": "translation" } }],
+                "samplers": [{ "input": input, "output": output }],
+            }),
+        );
+
+        let compiled_scene = GltfScene::from_slice(&document.to_glb())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let translation =
+            compiled_scene.skins[0].inverse_bind_matrices[0] * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        assert_eq!(
+            [translation.x(), translation.y(), translation.z()],
+            [1.0, 2.0, 3.0]
+        );
+        let channel = &compiled_scene.animations[0].channels[0];
+        assert_eq!(channel.inputs, [0.0, 1.0]);
+        assert_eq!(channel.outputs, [0.0, 0.0, 0.0, 4.0, 5.0, 6.0]);
     }
 }
@@ -0,0 +1,116 @@
+use math::mat::Mat4;
+
+use super::transform::Transform;
+
+#[derive(Debug, Default)]
+pub struct SceneNode {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub transform: Transform,
+    // Overrides the default morph target weights of the node's mesh when not empty
+    pub morph_weights: Vec<f32>,
+    // Indices into the compiled scene's mesh draws, cameras and lights
+    pub mesh_draws: Vec<usize>,
+    pub camera: Option<usize>,
+    pub light: Option<usize>,
+}
+
+// Node hierarchy of a compiled scene. Local transforms are edited through the graph so
+// that only the dirty sub-trees have their world transforms recomputed on `update`.
+#[derive(Debug, Default)]
+pub struct SceneGraph {
+    nodes: Vec<SceneNode>,
+    roots: Vec<usize>,
+    world_transforms: Vec<Mat4>,
+    dirty: Vec<bool>,
+}
+
+impl SceneGraph {
+    // Every node without a parent is a root
+    pub fn new(nodes: Vec<SceneNode>) -> Self {
+        let mut is_child = vec![false; nodes.len()];
+        for node in &nodes {
+            for child in &node.children {
+                is_child[*child] = true;
+            }
+        }
+        let roots = (0..nodes.len()).filter(|idx| !is_child[*idx]).collect();
+        Self::with_roots(nodes, roots)
+    }
+
+    // For documents with several scenes, where only the nodes under `roots` belong to the
+    // compiled one. Parents are taken from the children lists, world transforms are ready
+    // on return
+    pub fn with_roots(mut nodes: Vec<SceneNode>, roots: Vec<usize>) -> Self {
+        for idx in 0..nodes.len() {
+            for child in nodes[idx].children.clone() {
+                nodes[child].parent = Some(idx);
+            }
+        }
+        let mut graph = Self {
+            world_transforms: vec![Mat4::identity(); nodes.len()],
+            dirty: vec![true; nodes.len()],
+            nodes,
+            roots,
+        };
+        graph.update();
+        graph
+    }
+
+    pub fn nodes(&self) -> &[SceneNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, idx: usize) -> &SceneNode {
+        &self.nodes[idx]
+    }
+
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+    }
+
+    pub fn set_transform(&mut self, idx: usize, transform: Transform) {
+        if self.nodes[idx].transform != transform {
+            self.nodes[idx].transform = transform;
+            self.dirty[idx] = true;
+        }
+    }
+
+    pub fn world_transform(&self, idx: usize) -> Mat4 {
+        self.world_transforms[idx]
+    }
+
+    pub fn world_transforms(&self) -> &[Mat4] {
+        &self.world_transforms
+    }
+
+    // Recomputes the world transforms of dirty nodes and their descendants,
+    // returning the nodes that changed
+    pub fn update(&mut self) -> Vec<usize> {
+        let mut updated = Vec::new();
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|idx| (*idx, Mat4::identity(), false))
+            .collect::<Vec<_>>();
+        while let Some((idx, parent_transform, parent_dirty)) = stack.pop() {
+            let dirty = parent_dirty || self.dirty[idx];
+            if dirty {
+                self.world_transforms[idx] = parent_transform * self.nodes[idx].transform.matrix();
+                self.dirty[idx] = false;
+                updated.push(idx);
+            }
+            for child in &self.nodes[idx].children {
+                stack.push((*child, self.world_transforms[idx], dirty));
+            }
+        }
+        updated
+    }
+}
@@ -0,0 +1,212 @@
+use super::gltf_scene::{ImageData, ImageFormat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipFilter {
+    // Averages 2x2 blocks, cheap but blurry and prone to aliasing
+    Box,
+    // Kaiser windowed sinc, keeps minified textures sharper
+    Kaiser,
+}
+
+// Colour textures are filtered in linear space so that they don't darken as they shrink.
+// Data textures (normals, metallic-roughness, occlusion) are filtered as they are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+const KAISER_WIDTH: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x_squared = x * x * 0.25;
+    for k in 1..32 {
+        term *= half_x_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+    sum
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+fn kaiser(x: f32) -> f32 {
+    let t = x / KAISER_WIDTH;
+    if t.abs() >= 1.0 {
+        return 0.0;
+    }
+    sinc(x) * bessel_i0(KAISER_ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_ALPHA)
+}
+
+fn decode(format: ImageFormat, bytes: &[u8], color_space: ColorSpace) -> Vec<[f32; 4]> {
+    let channels = match format {
+        ImageFormat::Rgba8 => bytes
+            .iter()
+            .map(|channel| *channel as f32 / 255.0)
+            .collect::<Vec<_>>(),
+        ImageFormat::Rgba16 => bytes
+            .chunks_exact(2)
+            .map(|channel| u16::from_ne_bytes([channel[0], channel[1]]) as f32 / 65535.0)
+            .collect(),
+        ImageFormat::Rgba32F => bytes
+            .chunks_exact(4)
+            .map(|channel| f32::from_ne_bytes([channel[0], channel[1], channel[2], channel[3]]))
+            .collect(),
+    };
+    channels
+        .chunks_exact(4)
+        .map(|pixel| {
+            let mut pixel = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            // Float images are already linear
+            if color_space == ColorSpace::Srgb && format != ImageFormat::Rgba32F {
+                for channel in &mut pixel[0..3] {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+            pixel
+        })
+        .collect()
+}
+
+fn encode(format: ImageFormat, pixels: &[[f32; 4]], color_space: ColorSpace) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pixels.len() * format.bytes_per_pixel());
+    for pixel in pixels {
+        for (idx, channel) in pixel.iter().enumerate() {
+            let channel =
+                if color_space == ColorSpace::Srgb && format != ImageFormat::Rgba32F && idx < 3 {
+                    linear_to_srgb(channel.clamp(0.0, 1.0))
+                } else {
+                    *channel
+                };
+            match format {
+                ImageFormat::Rgba8 => bytes.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8),
+                ImageFormat::Rgba16 => bytes.extend_from_slice(
+                    &((channel.clamp(0.0, 1.0) * 65535.0).round() as u16).to_ne_bytes(),
+                ),
+                ImageFormat::Rgba32F => bytes.extend_from_slice(&channel.to_ne_bytes()),
+            }
+        }
+    }
+    bytes
+}
+
+// Weights of the source texels contributing to every destination texel along one axis
+fn axis_weights(src_len: u32, dst_len: u32, filter: MipFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_len as f32 / dst_len as f32;
+    (0..dst_len)
+        .map(|dst| {
+            let center = (dst as f32 + 0.5) * scale;
+            let mut weights = match filter {
+                MipFilter::Box => {
+                    let begin = (center - scale * 0.5).floor().max(0.0) as usize;
+                    let end = ((center + scale * 0.5).ceil() as usize).min(src_len as usize);
+                    (begin..end).map(|src| (src, 1.0)).collect::<Vec<_>>()
+                }
+                MipFilter::Kaiser => {
+                    let radius = KAISER_WIDTH * scale;
+                    let begin = (center - radius).floor() as i64;
+                    let end = (center + radius).ceil() as i64;
+                    (begin..end)
+                        .map(|src| {
+                            let weight = kaiser((src as f32 + 0.5 - center) / scale);
+                            // Clamp to edge
+                            (src.clamp(0, src_len as i64 - 1) as usize, weight)
+                        })
+                        .filter(|(_, weight)| *weight != 0.0)
+                        .collect()
+                }
+            };
+            let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+            for (_, weight) in &mut weights {
+                *weight /= total;
+            }
+            weights
+        })
+        .collect()
+}
+
+// Separable downsample, horizontal pass first
+fn downsample(
+    pixels: &[[f32; 4]],
+    (width, height): (u32, u32),
+    (dst_width, dst_height): (u32, u32),
+    filter: MipFilter,
+) -> Vec<[f32; 4]> {
+    let x_weights = axis_weights(width, dst_width, filter);
+    let y_weights = axis_weights(height, dst_height, filter);
+
+    let mut horizontal = vec![[0.0; 4]; (dst_width * height) as usize];
+    for y in 0..height as usize {
+        for (x, weights) in x_weights.iter().enumerate() {
+            let dst = &mut horizontal[y * dst_width as usize + x];
+            for (src, weight) in weights {
+                let src = pixels[y * width as usize + src];
+                for channel in 0..4 {
+                    dst[channel] += src[channel] * weight;
+                }
+            }
+        }
+    }
+
+    let mut vertical = vec![[0.0; 4]; (dst_width * dst_height) as usize];
+    for (y, weights) in y_weights.iter().enumerate() {
+        for x in 0..dst_width as usize {
+            let dst = &mut vertical[y * dst_width as usize + x];
+            for (src, weight) in weights {
+                let src = horizontal[src * dst_width as usize + x];
+                for channel in 0..4 {
+                    dst[channel] += src[channel] * weight;
+                }
+            }
+        }
+    }
+    vertical
+}
+
+// Every level is filtered from the one above it, kept in linear floats so that rounding
+// doesn't accumulate down the chain
+pub(crate) fn build_mip_chain(
+    image: &ImageData,
+    filter: MipFilter,
+    color_space: ColorSpace,
+) -> Vec<Vec<u8>> {
+    let mut pixels = decode(image.format, &image.bytes, color_space);
+    let mut size = (image.width, image.height);
+    (1..image.num_mip_levels())
+        .map(|level| {
+            let mip_size = image.mip_size(level);
+            pixels = downsample(&pixels, size, mip_size, filter);
+            size = mip_size;
+            encode(image.format, &pixels, color_space)
+        })
+        .collect()
+}
@@ -1,6 +1,42 @@
+mod accessor;
+pub mod animation;
+mod bounds;
+mod cache;
 mod compiled_scene;
+#[cfg(test)]
+mod fixtures;
+mod geometry;
+mod gltf_export;
 mod gltf_scene;
+pub mod graph;
+mod mipmap;
+mod morph;
+mod obj_scene;
+mod ply_scene;
+mod skin;
+mod source;
+mod transform;
 
-pub use compiled_scene::CompiledScene;
-pub use gltf_scene::GltfScene;
-pub use gltf_scene::ImageData as GltfImageData;
+pub use accessor::{AccessorFormat, ComponentType};
+pub use bounds::{Aabb, BoundingSphere};
+pub use cache::CachedScene;
+pub use compiled_scene::{
+    AlphaMode, CompiledScene, Light, LightKind, Material, NormalTexture, OcclusionTexture,
+    PbrMetallicRoughness, Primitive, TextureInfo, TextureTransform, Topology,
+};
+pub use geometry::{
+    flat_normals, generate_tangents, smooth_normals, GeneratedTangents, NormalGeneration,
+};
+pub use gltf_export::{to_glb, write_glb, write_gltf};
+pub use gltf_scene::{
+    AttributeFormat, Filter, GltfScene, GltfSceneError, PrimitiveReport, SamplerData, SceneInfo,
+    SceneSelector, TextureData, WrapMode,
+};
+pub use gltf_scene::{ImageData as GltfImageData, ImageFormat};
+pub use mipmap::{ColorSpace, MipFilter};
+pub use morph::{MorphTarget, MorphTargets};
+pub use obj_scene::{ObjScene, ObjSceneError};
+pub use ply_scene::{PlyScene, PlySceneError};
+pub use skin::{skin_vertex, Skin};
+pub use source::{load_cached_scene, load_scene, SceneSource, SceneSourceError};
+pub use transform::{slerp, Transform};
@@ -0,0 +1,84 @@
+use super::{accessor::read_floats, geometry::unweld};
+use math::vec::{Vec3, Vec4};
+
+#[derive(Debug)]
+pub struct MorphTarget {
+    // Per-vertex displacements, empty if the target doesn't displace that attribute
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+#[derive(Debug)]
+pub struct MorphTargets {
+    pub targets: Vec<MorphTarget>,
+    pub default_weights: Vec<f32>,
+}
+
+impl MorphTargets {
+    pub fn new(
+        prim: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+        default_weights: Option<&[f32]>,
+    ) -> Option<Self> {
+        let read = |accessor: Option<gltf::Accessor>| {
+            accessor
+                .and_then(|accessor| read_floats(&accessor, buffers))
+                .map(|data| {
+                    data.into_iter()
+                        .map(|[x, y, z, _]| Vec3::new(x, y, z))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let targets = prim
+            .morph_targets()
+            .map(|target| MorphTarget {
+                positions: read(target.positions()),
+                normals: read(target.normals()),
+            })
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return None;
+        }
+        let default_weights = default_weights
+            .map(|weights| weights.to_vec())
+            .unwrap_or_else(|| vec![0.0; targets.len()]);
+        Some(Self {
+            targets,
+            default_weights,
+        })
+    }
+
+    pub fn unweld(&mut self, indices: &[u32]) {
+        for target in &mut self.targets {
+            if !target.positions.is_empty() {
+                target.positions = unweld(&target.positions, indices);
+            }
+            if !target.normals.is_empty() {
+                target.normals = unweld(&target.normals, indices);
+            }
+        }
+    }
+
+    // `positions` and `normals` are the vertices of the draw the targets belong to
+    pub fn apply(&self, weights: &[f32], positions: &mut [Vec4], normals: &mut [Vec3]) {
+        for (target, weight) in self.targets.iter().zip(weights) {
+            if *weight == 0.0 {
+                continue;
+            }
+            for (position, delta) in positions.iter_mut().zip(&target.positions) {
+                *position += Vec4::new(delta.x(), delta.y(), delta.z(), 0.0) * *weight;
+            }
+            for (normal, delta) in normals.iter_mut().zip(&target.normals) {
+                *normal += *delta * *weight;
+            }
+        }
+        if self.targets.iter().any(|target| !target.normals.is_empty()) {
+            for normal in normals.iter_mut() {
+                if normal.dot(normal) > 0.0 {
+                    *normal = normal.normalized();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use crate::camera::{Camera, CameraType};
+
+use super::{
+    compiled_scene::{
+        AlphaMode, CompiledScene, Material, MeshDraw, NormalTexture, TextureInfo, Topology,
+    },
+    geometry::NormalGeneration,
+    gltf_scene::{decode_image, GltfSceneError, ImageData, SamplerData, TextureData},
+    graph::{SceneGraph, SceneNode},
+    source::{MeshData, SceneSource},
+};
+use anyhow::Result;
+use math::vec::{Vec2, Vec3, Vec4};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ObjSceneError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ImageError(#[from] GltfSceneError),
+    #[error("Obj line {line}: {message}")]
+    ParseError { line: usize, message: String },
+    #[error("Mtl file {file} line {line}: {message}")]
+    MtlParseError {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    #[error("External reference {0} can't be resolved without a base path")]
+    ExternalReference(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ObjVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Faces, lines or points sharing an object or group and a material, they become one primitive
+struct FaceGroup {
+    node: usize,
+    material_idx: u32,
+    topology: Topology,
+    vertices: Vec<ObjVertex>,
+}
+
+pub struct ObjScene {
+    positions: Vec<Vec4>,
+    // One per position, white unless the file uses the `v x y z r g b` extension
+    colors: Vec<Vec4>,
+    uvs: Vec<Vec2>,
+    normals: Vec<Vec3>,
+    node_names: Vec<Option<String>>,
+    face_groups: Vec<FaceGroup>,
+    materials: Vec<Material>,
+    images: Vec<ImageData>,
+    textures: Vec<TextureData>,
+    // Material libraries and the images they reference
+    external_files: Vec<PathBuf>,
+    normal_generation: NormalGeneration,
+}
+
+impl ObjScene {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let base = path.parent().unwrap_or_else(|| Path::new("./"));
+        Ok(Self::from_obj(&std::fs::read(path)?, Some(base))?)
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        Ok(Self::from_obj(slice, None)?)
+    }
+
+    pub(super) fn from_obj(slice: &[u8], base: Option<&Path>) -> Result<Self, ObjSceneError> {
+        let mut scene = Self {
+            positions: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            node_names: Vec::new(),
+            face_groups: Vec::new(),
+            materials: Vec::new(),
+            images: Vec::new(),
+            textures: Vec::new(),
+            external_files: Vec::new(),
+            normal_generation: NormalGeneration::default(),
+        };
+        let mut material_indices = HashMap::new();
+        let mut image_indices = HashMap::new();
+        let mut face_group_indices = HashMap::new();
+        let mut current_node = None;
+        let mut pending_name = None;
+        let mut current_material = None;
+
+        let text = String::from_utf8_lossy(slice);
+        for (line_idx, line) in text.lines().enumerate() {
+            let error = |message: String| ObjSceneError::ParseError {
+                line: line_idx + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (keyword, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let args = args.trim();
+            match keyword {
+                "v" => {
+                    let values = parse_floats(args).map_err(error)?;
+                    match values.as_slice() {
+                        // The optional w component is ignored
+                        [x, y, z] | [x, y, z, _] => {
+                            scene.positions.push(Vec4::new(*x, *y, *z, 1.0));
+                            scene.colors.push(Vec4::new(1.0, 1.0, 1.0, 1.0));
+                        }
+                        [x, y, z, r, g, b, ..] => {
+                            scene.positions.push(Vec4::new(*x, *y, *z, 1.0));
+                            scene.colors.push(Vec4::new(*r, *g, *b, 1.0));
+                        }
+                        _ => return Err(error(format!("Invalid vertex {:?}", args))),
+                    }
+                }
+                "vt" => match parse_floats(args).map_err(error)?.as_slice() {
+                    // Obj's v axis points up, gltf's points down
+                    [u] => scene.uvs.push(Vec2::new(*u, 1.0)),
+                    [u, v, ..] => scene.uvs.push(Vec2::new(*u, 1.0 - v)),
+                    _ => return Err(error(format!("Invalid texture coordinate {:?}", args))),
+                },
+                "vn" => match parse_floats(args).map_err(error)?.as_slice() {
+                    [x, y, z] => scene.normals.push(Vec3::new(*x, *y, *z)),
+                    _ => return Err(error(format!("Invalid normal {:?}", args))),
+                },
+                "o" | "g" => {
+                    current_node = None;
+                    pending_name = (!args.is_empty()).then(|| args.to_owned());
+                }
+                "usemtl" => current_material = material_indices.get(args).copied(),
+                "mtllib" => {
+                    for file in args.split_whitespace() {
+                        let base =
+                            base.ok_or_else(|| ObjSceneError::ExternalReference(file.to_owned()))?;
+                        let path = base.join(file);
+                        let mtl = std::fs::read(&path)?;
+                        scene.external_files.push(path.clone());
+                        scene.parse_mtl(
+                            &String::from_utf8_lossy(&mtl),
+                            &path,
+                            &mut material_indices,
+                            &mut image_indices,
+                        )?;
+                    }
+                }
+                "f" | "l" | "p" => {
+                    let vertices = args
+                        .split_whitespace()
+                        .map(|vertex| scene.parse_face_vertex(vertex))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(error)?;
+                    let (topology, min_vertices) = match keyword {
+                        "f" => (Topology::Triangles, 3),
+                        "l" => (Topology::Lines, 2),
+                        _ => (Topology::Points, 1),
+                    };
+                    if vertices.len() < min_vertices {
+                        return Err(error(format!(
+                            "{:?} needs at least {} vertices",
+                            line, min_vertices
+                        )));
+                    }
+
+                    let node = *current_node.get_or_insert_with(|| {
+                        scene.node_names.push(pending_name.take());
+                        scene.node_names.len() - 1
+                    });
+                    // The default material is added after the file's materials
+                    let material_idx = current_material.map(|idx| idx as u32);
+                    let face_group = *face_group_indices
+                        .entry((node, material_idx, topology))
+                        .or_insert_with(|| {
+                            scene.face_groups.push(FaceGroup {
+                                node,
+                                material_idx: 0,
+                                topology,
+                                vertices: Vec::new(),
+                            });
+                            scene.face_groups.len() - 1
+                        });
+                    let group_vertices = &mut scene.face_groups[face_group].vertices;
+                    match topology {
+                        // Polygons are assumed to be convex
+                        Topology::Triangles => {
+                            for idx in 1..vertices.len() - 1 {
+                                group_vertices.extend([
+                                    vertices[0],
+                                    vertices[idx],
+                                    vertices[idx + 1],
+                                ]);
+                            }
+                        }
+                        // Polylines are split into segments
+                        Topology::Lines => {
+                            for segment in vertices.windows(2) {
+                                group_vertices.extend([segment[0], segment[1]]);
+                            }
+                        }
+                        Topology::Points => group_vertices.extend(vertices),
+                    }
+                }
+                // Smoothing groups, curves and anything else are ignored
+                _ => {}
+            }
+        }
+
+        // Faces without a known material use the default one, stored after the others
+        let default_material_idx = scene.materials.len() as u32;
+        for ((_, material_idx, _), face_group) in face_group_indices {
+            scene.face_groups[face_group].material_idx =
+                material_idx.unwrap_or(default_material_idx);
+        }
+        scene.materials.push(Material::default());
+        // Add 1x1 white texture to the back
+        scene.images.push(ImageData::white());
+        Ok(scene)
+    }
+
+    // `v`, `v/vt`, `v//vn` or `v/vt/vn`, with 1-based or negative, relative, indices
+    fn parse_face_vertex(&self, vertex: &str) -> Result<ObjVertex, String> {
+        let mut indices = vertex.split('/');
+        let position = match indices.next() {
+            Some(index) => resolve_index(index, self.positions.len())?,
+            None => return Err(format!("Invalid face vertex {:?}", vertex)),
+        };
+        let uv = match indices.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve_index(index, self.uvs.len())?),
+        };
+        let normal = match indices.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve_index(index, self.normals.len())?),
+        };
+        Ok(ObjVertex {
+            position,
+            uv,
+            normal,
+        })
+    }
+
+    fn parse_mtl(
+        &mut self,
+        text: &str,
+        path: &Path,
+        material_indices: &mut HashMap<String, usize>,
+        image_indices: &mut HashMap<PathBuf, usize>,
+    ) -> Result<(), ObjSceneError> {
+        let base = path.parent().unwrap_or_else(|| Path::new("./"));
+        let Self {
+            materials,
+            images,
+            textures,
+            external_files,
+            ..
+        } = self;
+        for (line_idx, line) in text.lines().enumerate() {
+            let error = |message: String| ObjSceneError::MtlParseError {
+                file: path.display().to_string(),
+                line: line_idx + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (keyword, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let args = args.trim();
+
+            if keyword == "newmtl" {
+                material_indices.insert(args.to_owned(), materials.len());
+                let mut material = Material {
+                    name: Some(args.to_owned()),
+                    ..Default::default()
+                };
+                // Obj materials describe dielectrics unless they say otherwise
+                material.metallic_roughness.metallic_factor = 0.0;
+                materials.push(material);
+                continue;
+            }
+            let material = match materials.last_mut() {
+                Some(material) => material,
+                None if keyword.is_empty() => continue,
+                None => return Err(error(format!("{} before newmtl", keyword))),
+            };
+            let pbr = &mut material.metallic_roughness;
+            let float = || -> Result<f32, ObjSceneError> {
+                match parse_floats(args).map_err(error)?.as_slice() {
+                    [value] => Ok(*value),
+                    _ => Err(error(format!("Expected one value, got {:?}", args))),
+                }
+            };
+            let color = || -> Result<Vec3, ObjSceneError> {
+                match parse_floats(args).map_err(error)?.as_slice() {
+                    [r, g, b] => Ok(Vec3::new(*r, *g, *b)),
+                    // A single value is used for all channels
+                    [value] => Ok(Vec3::new(*value, *value, *value)),
+                    _ => Err(error(format!("Invalid color {:?}", args))),
+                }
+            };
+
+            match keyword {
+                "Kd" => {
+                    let color = color()?;
+                    let alpha = pbr.base_color_factor.w();
+                    pbr.base_color_factor = Vec4::new(color.x(), color.y(), color.z(), alpha);
+                }
+                "d" | "Tr" => {
+                    let alpha = if keyword == "d" {
+                        float()?
+                    } else {
+                        1.0 - float()?
+                    };
+                    let color = pbr.base_color_factor;
+                    pbr.base_color_factor = Vec4::new(color.x(), color.y(), color.z(), alpha);
+                    material.alpha_mode = if alpha < 1.0 {
+                        AlphaMode::Blend
+                    } else {
+                        AlphaMode::Opaque
+                    };
+                }
+                "Ke" => material.emissive_factor = color()?,
+                // Blinn-Phong exponent to roughness, the PBR extension's Pr overrides it
+                "Ns" => pbr.roughness_factor = (2.0 / (float()?.max(0.0) + 2.0)).sqrt(),
+                "Pr" => pbr.roughness_factor = float()?,
+                "Pm" => pbr.metallic_factor = float()?,
+                "map_Kd" | "map_Ke" | "map_Bump" | "map_bump" | "bump" | "norm" => {
+                    let (file, bump_scale) = parse_texture_map(args);
+                    if file.is_empty() {
+                        return Err(error(format!("{} without a file", keyword)));
+                    }
+                    let image_path = base.join(file);
+                    let image_index = match image_indices.get(&image_path) {
+                        Some(image_index) => *image_index,
+                        None => {
+                            images.push(decode_image(&std::fs::read(&image_path)?)?);
+                            external_files.push(image_path.clone());
+                            image_indices.insert(image_path, images.len() - 1);
+                            images.len() - 1
+                        }
+                    };
+                    textures.push(TextureData {
+                        image_index,
+                        sampler: SamplerData::default(),
+                    });
+                    let texture = TextureInfo {
+                        texture_index: textures.len() - 1,
+                        tex_coord: 0,
+                        transform: None,
+                    };
+                    match keyword {
+                        "map_Kd" => pbr.base_color_texture = Some(texture),
+                        "map_Ke" => {
+                            material.emissive_texture = Some(texture);
+                            // Without Ke the texture would have nothing to scale
+                            if material.emissive_factor == Vec3::new(0.0, 0.0, 0.0) {
+                                material.emissive_factor = Vec3::new(1.0, 1.0, 1.0);
+                            }
+                        }
+                        _ => {
+                            material.normal_texture = Some(NormalTexture {
+                                texture,
+                                scale: bump_scale,
+                            })
+                        }
+                    }
+                }
+                // Ambient and specular colors, illumination models and other maps are ignored
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn with_normal_generation(mut self, normal_generation: NormalGeneration) -> Self {
+        self.normal_generation = normal_generation;
+        self
+    }
+
+    // Every object or group becomes a root node, with one primitive per material and topology
+    pub fn compile(&self) -> CompiledScene {
+        let mut compiled_scene = CompiledScene {
+            materials: self.materials.clone(),
+            ..Default::default()
+        };
+        let mut nodes = self
+            .node_names
+            .iter()
+            .map(|name| SceneNode {
+                name: name.clone(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        for face_group in &self.face_groups {
+            // Normals are only used if every vertex has one, otherwise they are generated
+            let has_normals = face_group
+                .vertices
+                .iter()
+                .all(|vertex| vertex.normal.is_some());
+            let mut mesh = MeshData {
+                topology: face_group.topology,
+                ..Default::default()
+            };
+            let mut normals = Vec::new();
+            let mut vertex_indices = HashMap::new();
+            for vertex in &face_group.vertices {
+                let idx = *vertex_indices.entry(*vertex).or_insert_with(|| {
+                    mesh.positions.push(self.positions[vertex.position]);
+                    mesh.colors.push(self.colors[vertex.position]);
+                    mesh.uvs.push(match vertex.uv {
+                        Some(uv) => self.uvs[uv],
+                        None => Vec2::new(0.0, 0.0),
+                    });
+                    if let Some(normal) = vertex.normal {
+                        normals.push(self.normals[normal]);
+                    }
+                    mesh.positions.len() as u32 - 1
+                });
+                mesh.indices.push(idx);
+            }
+            mesh.normals = has_normals.then_some(normals);
+
+            let primitive_idx = mesh.push_primitive(
+                face_group.material_idx,
+                self.normal_generation,
+                &mut compiled_scene,
+            );
+            nodes[face_group.node]
+                .mesh_draws
+                .push(compiled_scene.mesh_draws.len());
+            compiled_scene.mesh_draws.push(MeshDraw {
+                primitive_idx,
+                material_idx: face_group.material_idx,
+                topology: face_group.topology,
+                node_idx: face_group.node as u32,
+                skin_idx: None,
+            });
+        }
+        compiled_scene.graph = SceneGraph::new(nodes);
+
+        compiled_scene
+            .cameras
+            .push(Camera::from_type(CameraType::perspective(
+                Default::default(),
+            )));
+        compiled_scene
+    }
+
+    pub fn image_data(&self) -> &[ImageData] {
+        &self.images
+    }
+
+    pub fn texture_data(&self) -> &[TextureData] {
+        &self.textures
+    }
+
+    pub fn external_files(&self) -> &[PathBuf] {
+        &self.external_files
+    }
+}
+
+impl SceneSource for ObjScene {
+    fn compile(&self) -> Result<CompiledScene> {
+        Ok(ObjScene::compile(self))
+    }
+
+    fn image_data(&self) -> &[ImageData] {
+        ObjScene::image_data(self)
+    }
+
+    fn texture_data(&self) -> &[TextureData] {
+        ObjScene::texture_data(self)
+    }
+
+    fn external_files(&self) -> &[PathBuf] {
+        ObjScene::external_files(self)
+    }
+}
+
+fn parse_floats(args: &str) -> Result<Vec<f32>, String> {
+    args.split_whitespace()
+        .map(|value| {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid number {:?}", value))
+        })
+        .collect()
+}
+
+fn resolve_index(index: &str, len: usize) -> Result<usize, String> {
+    let resolved = match index.parse::<i64>() {
+        Ok(index) if index > 0 => Some(index as usize - 1),
+        Ok(index) if index < 0 => len.checked_sub(index.unsigned_abs() as usize),
+        _ => return Err(format!("Invalid index {:?}", index)),
+    };
+    resolved.filter(|resolved| *resolved < len).ok_or_else(|| {
+        format!(
+            "Index {} is out of bounds, only {} were defined",
+            index, len
+        )
+    })
+}
+
+// Splits `[-option args...] file` into the file name and the bump multiplier
+fn parse_texture_map(args: &str) -> (String, f32) {
+    let mut tokens = args.split_whitespace().peekable();
+    let mut bump_scale = 1.0;
+    while let Some(option) = tokens.next_if(|token| token.starts_with('-')) {
+        match option {
+            "-bm" => {
+                bump_scale = tokens
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(1.0)
+            }
+            "-mm" => {
+                tokens.next();
+                tokens.next();
+            }
+            // Up to three numbers
+            "-o" | "-s" | "-t" => {
+                for _ in 0..3 {
+                    tokens.next_if(|token| token.parse::<f32>().is_ok());
+                }
+            }
+            _ => {
+                tokens.next();
+            }
+        }
+    }
+    // File names may contain spaces, and paths written on windows use backslashes
+    let file = tokens.collect::<Vec<_>>().join(" ").replace('\\', "/");
+    (file, bump_scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: usize, uv: Option<usize>, normal: Option<usize>) -> ObjVertex {
+        ObjVertex {
+            position,
+            uv,
+            normal,
+        }
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let obj = "o tri\r\nv 0 0 0\r\nv 1 0 0\r\nv 0 1 0\r\nvt 0 0\r\nvt 1 0\r\nvt 0 1\r\n\
+                   vn 0 0 1\r\nf 1/1/1 2/2/1 3/3/1\r\n";
+        let scene = ObjScene::from_obj(obj.as_bytes(), None).unwrap();
+        assert_eq!(scene.positions.len(), 3);
+        assert_eq!(scene.uvs.len(), 3);
+        assert_eq!(scene.normals.len(), 1);
+        assert_eq!(scene.node_names, [Some("tri".to_owned())]);
+        assert_eq!(
+            scene.face_groups[0].vertices,
+            [
+                vertex(0, Some(0), Some(0)),
+                vertex(1, Some(1), Some(0)),
+                vertex(2, Some(2), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_indices_are_relative() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf -3/-3 -2/-2 -1/-1\n\
+                   v 1 1 0\nf -4 -2 -1\n";
+        let scene = ObjScene::from_obj(obj.as_bytes(), None).unwrap();
+        assert_eq!(
+            scene.face_groups[0].vertices,
+            [
+                vertex(0, Some(0), None),
+                vertex(1, Some(1), None),
+                vertex(2, Some(2), None),
+                vertex(0, None, None),
+                vertex(2, None, None),
+                vertex(3, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_range_indices_are_an_error() {
+        for obj in [
+            "v 0 0 0\nv 1 0 0\nf 1 2 3\n",
+            "v 0 0 0\nv 1 0 0\nf -3 1 2\n",
+        ] {
+            assert!(matches!(
+                ObjScene::from_obj(obj.as_bytes(), None),
+                Err(ObjSceneError::ParseError { line: 3, .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use crate::camera::{Camera, CameraType};
+
+use super::{
+    compiled_scene::{CompiledScene, Material, MeshDraw, Topology},
+    geometry::NormalGeneration,
+    gltf_scene::{ImageData, TextureData},
+    graph::{SceneGraph, SceneNode},
+    source::{MeshData, SceneSource},
+};
+use anyhow::Result;
+use math::vec::{Vec2, Vec3, Vec4};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PlySceneError {
+    #[error("Ply file has no header")]
+    MissingHeader,
+    #[error("Invalid ply header line {0:?}")]
+    InvalidHeader(String),
+    #[error("Unsupported ply format {0:?}")]
+    UnsupportedFormat(String),
+    #[error("Ply data ended before all {0} elements were read")]
+    UnexpectedEnd(String),
+    #[error("Invalid ply value {0:?}")]
+    InvalidValue(String),
+    #[error("Ply file has no vertex positions")]
+    MissingPositions,
+    #[error("Invalid face vertex index {0}")]
+    InvalidIndex(f64),
+    #[error("Face references vertex {index}, but there are only {num_vertices}")]
+    IndexOutOfBounds { index: usize, num_vertices: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PropertyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PropertyType {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    // Integer colors cover their type's whole range, float colors are already in 0..1
+    fn normalize(&self, value: f64) -> f32 {
+        match self {
+            Self::U8 => (value / u8::MAX as f64) as f32,
+            Self::U16 => (value / u16::MAX as f64) as f32,
+            _ => value as f32,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    ty: PropertyType,
+    // Type of the item count for list properties
+    list_count: Option<PropertyType>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: PlyFormat,
+    elements: Vec<Element>,
+}
+
+fn parse_header(header: &str) -> Result<Header, PlySceneError> {
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(PlySceneError::MissingHeader);
+    }
+    let mut format = None;
+    let mut elements = Vec::<Element>::new();
+    for line in lines {
+        let invalid = || PlySceneError::InvalidHeader(line.to_owned());
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(PlySceneError::UnsupportedFormat(name.to_string())),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid())?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, ty, name] => elements
+                .last_mut()
+                .ok_or_else(invalid)?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    ty: PropertyType::from_name(ty).ok_or_else(invalid)?,
+                    list_count: Some(PropertyType::from_name(count_ty).ok_or_else(invalid)?),
+                }),
+            ["property", ty, name] => {
+                elements
+                    .last_mut()
+                    .ok_or_else(invalid)?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        ty: PropertyType::from_name(ty).ok_or_else(invalid)?,
+                        list_count: None,
+                    })
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(Header {
+        format: format.ok_or(PlySceneError::MissingHeader)?,
+        elements,
+    })
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl<'a> Body<'a> {
+    // `None` once the data runs out
+    fn read(&mut self, ty: PropertyType) -> Result<Option<f64>, PlySceneError> {
+        match self {
+            Self::Ascii(tokens) => match tokens.next() {
+                Some(token) => token
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| PlySceneError::InvalidValue(token.to_owned())),
+                None => Ok(None),
+            },
+            Self::Binary { bytes, big_endian } => {
+                if bytes.len() < ty.size() {
+                    return Ok(None);
+                }
+                let (value, rest) = bytes.split_at(ty.size());
+                *bytes = rest;
+                let mut buffer = [0; 8];
+                buffer[..value.len()].copy_from_slice(value);
+                if *big_endian {
+                    buffer[..value.len()].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+                Ok(Some(match ty {
+                    PropertyType::I8 => b0 as i8 as f64,
+                    PropertyType::U8 => b0 as f64,
+                    PropertyType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    PropertyType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    PropertyType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PropertyType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PropertyType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PropertyType::F64 => f64::from_le_bytes(buffer),
+                }))
+            }
+        }
+    }
+}
+
+// Meshes and point clouds in ascii or binary ply. Only the vertex and face elements are used,
+// every vertex and face is loaded into one primitive.
+pub struct PlyScene {
+    positions: Vec<Vec4>,
+    colors: Vec<Vec4>,
+    uvs: Vec<Vec2>,
+    normals: Option<Vec<Vec3>>,
+    indices: Vec<u32>,
+    topology: Topology,
+    images: Vec<ImageData>,
+    normal_generation: NormalGeneration,
+}
+
+impl PlyScene {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_ply(&std::fs::read(path)?)?)
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        Ok(Self::from_ply(slice)?)
+    }
+
+    pub(super) fn from_ply(slice: &[u8]) -> Result<Self, PlySceneError> {
+        const END_HEADER: &[u8] = b"end_header";
+        let header_end = slice
+            .windows(END_HEADER.len())
+            .position(|window| window == END_HEADER)
+            .ok_or(PlySceneError::MissingHeader)?;
+        let body_start = slice[header_end..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(slice.len(), |newline| header_end + newline + 1);
+        let header = parse_header(&String::from_utf8_lossy(&slice[..header_end]))?;
+
+        let mut body = match header.format {
+            PlyFormat::Ascii => Body::Ascii(
+                std::str::from_utf8(&slice[body_start..])
+                    .map_err(|_| PlySceneError::InvalidValue("non utf-8 ascii data".to_owned()))?
+                    .split_ascii_whitespace(),
+            ),
+            PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => Body::Binary {
+                bytes: &slice[body_start..],
+                big_endian: header.format == PlyFormat::BinaryBigEndian,
+            },
+        };
+
+        let mut scene = Self {
+            positions: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            normals: None,
+            indices: Vec::new(),
+            topology: Topology::Triangles,
+            images: vec![ImageData::white()],
+            normal_generation: NormalGeneration::default(),
+        };
+        let mut normals = Vec::new();
+        let mut has_positions = false;
+        let mut values = Vec::new();
+        let mut list = Vec::new();
+        for element in &header.elements {
+            let property = |names: &[&str]| {
+                element
+                    .properties
+                    .iter()
+                    .position(|property| names.contains(&property.name.as_str()))
+            };
+            let xyz = [property(&["x"]), property(&["y"]), property(&["z"])];
+            let normal = [property(&["nx"]), property(&["ny"]), property(&["nz"])];
+            let color = [
+                property(&["red", "r", "diffuse_red"]),
+                property(&["green", "g", "diffuse_green"]),
+                property(&["blue", "b", "diffuse_blue"]),
+            ];
+            let alpha = property(&["alpha", "a"]);
+            let uv = [
+                property(&["s", "u", "texture_u", "texture_s"]),
+                property(&["t", "v", "texture_v", "texture_t"]),
+            ];
+            let vertex_indices = property(&["vertex_indices", "vertex_index"]);
+
+            for _ in 0..element.count {
+                values.clear();
+                list.clear();
+                for (idx, property) in element.properties.iter().enumerate() {
+                    let unexpected_end = || PlySceneError::UnexpectedEnd(element.name.clone());
+                    match property.list_count {
+                        Some(count_ty) => {
+                            let count = body.read(count_ty)?.ok_or_else(unexpected_end)?;
+                            for _ in 0..count as usize {
+                                let value = body.read(property.ty)?.ok_or_else(unexpected_end)?;
+                                if Some(idx) == vertex_indices {
+                                    list.push(value);
+                                }
+                            }
+                            values.push(0.0);
+                        }
+                        None => values.push(body.read(property.ty)?.ok_or_else(unexpected_end)?),
+                    }
+                }
+                let get = |idx: Option<usize>| idx.map(|idx| values[idx]);
+
+                if element.name == "vertex" {
+                    if let [Some(x), Some(y), Some(z)] = xyz.map(get) {
+                        has_positions = true;
+                        scene
+                            .positions
+                            .push(Vec4::new(x as f32, y as f32, z as f32, 1.0));
+                    } else {
+                        return Err(PlySceneError::MissingPositions);
+                    }
+                    if let [Some(x), Some(y), Some(z)] = normal.map(get) {
+                        normals.push(Vec3::new(x as f32, y as f32, z as f32));
+                    }
+                    let channel = |idx: Option<usize>, default: f32| match idx {
+                        Some(idx) => element.properties[idx].ty.normalize(values[idx]),
+                        None => default,
+                    };
+                    scene.colors.push(Vec4::new(
+                        channel(color[0], 1.0),
+                        channel(color[1], 1.0),
+                        channel(color[2], 1.0),
+                        channel(alpha, 1.0),
+                    ));
+                    scene.uvs.push(match uv.map(get) {
+                        // Like obj, the v axis points up
+                        [Some(u), Some(v)] => Vec2::new(u as f32, 1.0 - v as f32),
+                        _ => Vec2::new(0.0, 0.0),
+                    });
+                } else if element.name == "face" && list.len() >= 3 {
+                    let face = list
+                        .iter()
+                        .map(|index| {
+                            if *index >= 0.0 && *index <= u32::MAX as f64 && index.fract() == 0.0 {
+                                Ok(*index as u32)
+                            } else {
+                                Err(PlySceneError::InvalidIndex(*index))
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    // Polygons are assumed to be convex
+                    for idx in 1..face.len() - 1 {
+                        scene.indices.extend([face[0], face[idx], face[idx + 1]]);
+                    }
+                }
+            }
+        }
+
+        if !has_positions {
+            return Err(PlySceneError::MissingPositions);
+        }
+        let num_vertices = scene.positions.len();
+        if let Some(index) = scene
+            .indices
+            .iter()
+            .find(|index| **index as usize >= num_vertices)
+        {
+            return Err(PlySceneError::IndexOutOfBounds {
+                index: *index as usize,
+                num_vertices,
+            });
+        }
+        // Files without faces are point clouds
+        if scene.indices.is_empty() {
+            scene.topology = Topology::Points;
+            scene.indices = (0..num_vertices as u32).collect();
+        }
+        if normals.len() == num_vertices {
+            scene.normals = Some(normals);
+        }
+        Ok(scene)
+    }
+
+    pub fn with_normal_generation(mut self, normal_generation: NormalGeneration) -> Self {
+        self.normal_generation = normal_generation;
+        self
+    }
+
+    pub fn compile(&self) -> CompiledScene {
+        let mut compiled_scene = CompiledScene {
+            materials: vec![Material::default()],
+            ..Default::default()
+        };
+        let mesh = MeshData {
+            positions: self.positions.clone(),
+            colors: self.colors.clone(),
+            uvs: self.uvs.clone(),
+            normals: self.normals.clone(),
+            indices: self.indices.clone(),
+            topology: self.topology,
+        };
+        let primitive_idx = mesh.push_primitive(0, self.normal_generation, &mut compiled_scene);
+        compiled_scene.mesh_draws.push(MeshDraw {
+            primitive_idx,
+            material_idx: 0,
+            topology: self.topology,
+            node_idx: 0,
+            skin_idx: None,
+        });
+        compiled_scene.graph = SceneGraph::new(vec![SceneNode {
+            mesh_draws: vec![0],
+            ..Default::default()
+        }]);
+
+        compiled_scene
+            .cameras
+            .push(Camera::from_type(CameraType::perspective(
+                Default::default(),
+            )));
+        compiled_scene
+    }
+
+    pub fn image_data(&self) -> &[ImageData] {
+        &self.images
+    }
+
+    // Ply has no materials, so there are no textures, only the white image
+    pub fn texture_data(&self) -> &[TextureData] {
+        &[]
+    }
+
+    pub fn external_files(&self) -> &[PathBuf] {
+        &[]
+    }
+}
+
+impl SceneSource for PlyScene {
+    fn compile(&self) -> Result<CompiledScene> {
+        Ok(PlyScene::compile(self))
+    }
+
+    fn image_data(&self) -> &[ImageData] {
+        PlyScene::image_data(self)
+    }
+
+    fn texture_data(&self) -> &[TextureData] {
+        PlyScene::texture_data(self)
+    }
+
+    fn external_files(&self) -> &[PathBuf] {
+        PlyScene::external_files(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD_HEADER: &str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+                               property float y\nproperty float z\nelement face 1\n\
+                               property list uchar int vertex_indices\nend_header\n";
+
+    fn quad_ply(face: &str) -> String {
+        format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n{}\n", QUAD_HEADER, face)
+    }
+
+    fn quad(face: &str) -> Result<PlyScene, PlySceneError> {
+        PlyScene::from_ply(quad_ply(face).as_bytes())
+    }
+
+    #[test]
+    fn ascii_quad() {
+        let scene = quad("4 0 1 2 3").unwrap();
+        assert_eq!(scene.positions.len(), 4);
+        assert_eq!(scene.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(scene.topology, Topology::Triangles);
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let ply = quad_ply("4 0 1 2 3").replace('\n', "\r\n");
+        let scene = PlyScene::from_ply(ply.as_bytes()).unwrap();
+        assert_eq!(scene.positions.len(), 4);
+        assert_eq!(scene.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        let mut ply = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\n\
+                        property double x\nproperty float y\nproperty float z\n\
+                        property float nx\nproperty float ny\nproperty float nz\n\
+                        element face 1\nproperty list uchar ushort vertex_indices\nend_header\n"
+            .to_vec();
+        for idx in 0..3 {
+            ply.extend_from_slice(&(idx as f64).to_be_bytes());
+            for value in [2.0f32, 3.0, 0.0, 0.0, 1.0] {
+                ply.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        ply.push(3);
+        for index in [2u16, 1, 0] {
+            ply.extend_from_slice(&index.to_be_bytes());
+        }
+
+        let scene = PlyScene::from_ply(&ply).unwrap();
+        assert_eq!(scene.positions.len(), 3);
+        let position = scene.positions[2];
+        assert_eq!((position.x(), position.y(), position.z()), (2.0, 2.0, 3.0));
+        let normal = scene.normals.as_ref().unwrap()[1];
+        assert_eq!((normal.x(), normal.y(), normal.z()), (0.0, 0.0, 1.0));
+        assert_eq!(scene.indices, [2, 1, 0]);
+    }
+
+    #[test]
+    fn negative_indices_are_an_error() {
+        assert!(matches!(
+            quad("3 0 -1 2"),
+            Err(PlySceneError::InvalidIndex(index)) if index == -1.0
+        ));
+    }
+
+    #[test]
+    fn out_of_range_indices_are_an_error() {
+        assert!(matches!(
+            quad("3 0 1 4"),
+            Err(PlySceneError::IndexOutOfBounds {
+                index: 4,
+                num_vertices: 4
+            })
+        ));
+        assert!(matches!(
+            quad("3 0 1 5000000000"),
+            Err(PlySceneError::InvalidIndex(_))
+        ));
+    }
+}
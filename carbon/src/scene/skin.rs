@@ -0,0 +1,169 @@
+use super::accessor::read_matrices;
+use math::{
+    mat::Mat4,
+    vec::{Vec3, Vec4},
+};
+
+#[derive(Debug)]
+pub struct Skin {
+    pub name: Option<String>,
+    // Node indices of each joint, in the order referenced by the JOINTS_0 attribute
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+    pub skeleton: Option<usize>,
+}
+
+impl Skin {
+    pub fn new(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> Self {
+        let joints = skin.joints().map(|node| node.index()).collect::<Vec<_>>();
+        let inverse_bind_matrices = if let Some(data) = skin
+            .inverse_bind_matrices()
+            .and_then(|accessor| read_matrices(&accessor, buffers))
+        {
+            data.into_iter()
+                .map(|data| data.into())
+                .collect::<Vec<Mat4>>()
+        } else {
+            vec![Mat4::identity(); joints.len()]
+        };
+        Self {
+            name: skin.name().map(str::to_owned),
+            joints,
+            inverse_bind_matrices,
+            skeleton: skin.skeleton().map(|node| node.index()),
+        }
+    }
+
+    // `node_transforms` holds the world transform of every node in the document
+    pub fn joint_matrices(&self, node_transforms: &[Mat4]) -> Vec<Mat4> {
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(joint, inverse_bind_matrix)| node_transforms[*joint] * *inverse_bind_matrix)
+            .collect()
+    }
+}
+
+// Joints missing from `joint_matrices` are skipped and the remaining weights renormalized.
+// Vertices without any usable weight keep their unskinned position and normal.
+pub fn skin_vertex(
+    position: Vec4,
+    normal: Vec3,
+    joints: [u16; 4],
+    weights: Vec4,
+    joint_matrices: &[Mat4],
+) -> (Vec4, Vec3) {
+    let weights = [weights.x(), weights.y(), weights.z(), weights.w()];
+    let normal_4 = Vec4::new(normal.x(), normal.y(), normal.z(), 0.0);
+
+    let mut skinned_position = Vec4::new(0.0, 0.0, 0.0, 0.0);
+    let mut skinned_normal = Vec4::new(0.0, 0.0, 0.0, 0.0);
+    let mut total_weight = 0.0;
+    for (joint, weight) in joints.iter().zip(weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        if let Some(joint_matrix) = joint_matrices.get(*joint as usize) {
+            skinned_position += (*joint_matrix * position) * weight;
+            // TODO: Non-uniformly scaled joints need the inverse transpose here
+            skinned_normal += (*joint_matrix * normal_4) * weight;
+            total_weight += weight;
+        }
+    }
+    if total_weight <= 0.0 {
+        return (position, normal);
+    }
+    let skinned_position = skinned_position * (1.0 / total_weight);
+
+    let skinned_normal = Vec3::new(skinned_normal.x(), skinned_normal.y(), skinned_normal.z());
+    let skinned_normal = if skinned_normal.dot(&skinned_normal) > 0.0 {
+        skinned_normal.normalized()
+    } else {
+        skinned_normal
+    };
+    (skinned_position, skinned_normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Transform;
+
+    fn translation(x: f32, y: f32, z: f32) -> Mat4 {
+        Transform {
+            translation: Vec3::new(x, y, z),
+            ..Default::default()
+        }
+        .matrix()
+    }
+
+    // Joint 0 stays at the origin, joint 1 is bound at (0, 1, 0) and posed rotated
+    // 90 degrees around Z
+    fn two_joint_matrices() -> Vec<Mat4> {
+        let skin = Skin {
+            name: None,
+            joints: vec![0, 1],
+            inverse_bind_matrices: vec![Mat4::identity(), translation(0.0, -1.0, 0.0)],
+            skeleton: Some(0),
+        };
+        let half_angle = std::f32::consts::FRAC_PI_4;
+        let posed_joint = Transform {
+            translation: Vec3::new(0.0, 1.0, 0.0),
+            rotation: Vec4::new(0.0, 0.0, half_angle.sin(), half_angle.cos()),
+            ..Default::default()
+        };
+        skin.joint_matrices(&[Mat4::identity(), posed_joint.matrix()])
+    }
+
+    fn assert_vec_eq(actual: Vec4, expected: Vec4) {
+        let difference = actual - expected;
+        assert!(
+            difference.dot(&difference) < 1e-10,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn skin(joints: [u16; 4], weights: Vec4) -> (Vec4, Vec3) {
+        skin_vertex(
+            Vec4::new(0.0, 2.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            joints,
+            weights,
+            &two_joint_matrices(),
+        )
+    }
+
+    #[test]
+    fn single_joint() {
+        let (position, normal) = skin([1, 0, 0, 0], Vec4::new(1.0, 0.0, 0.0, 0.0));
+        assert_vec_eq(position, Vec4::new(-1.0, 1.0, 0.0, 1.0));
+        assert_vec_eq(
+            Vec4::new(normal.x(), normal.y(), normal.z(), 0.0),
+            Vec4::new(-1.0, 0.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn blended_joints() {
+        let (position, _) = skin([0, 1, 0, 0], Vec4::new(0.5, 0.5, 0.0, 0.0));
+        assert_vec_eq(position, Vec4::new(-0.5, 1.5, 0.0, 1.0));
+    }
+
+    #[test]
+    fn zero_weights_keep_the_bind_pose() {
+        let (position, normal) = skin([0, 1, 0, 0], Vec4::new(0.0, 0.0, 0.0, 0.0));
+        assert_vec_eq(position, Vec4::new(0.0, 2.0, 0.0, 1.0));
+        assert_eq!((normal.x(), normal.y(), normal.z()), (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn out_of_range_joints_are_skipped() {
+        let (position, _) = skin([7, 0, 0, 0], Vec4::new(1.0, 0.0, 0.0, 0.0));
+        assert_vec_eq(position, Vec4::new(0.0, 2.0, 0.0, 1.0));
+
+        let (position, _) = skin([7, 1, 0, 0], Vec4::new(0.5, 0.5, 0.0, 0.0));
+        assert_vec_eq(position, Vec4::new(-1.0, 1.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,115 @@
+use super::{
+    cache::{self, CachedScene},
+    compiled_scene::{CompiledScene, PrimitiveData, Topology},
+    geometry::NormalGeneration,
+    gltf_scene::{GltfScene, ImageData, TextureData},
+    obj_scene::ObjScene,
+    ply_scene::PlyScene,
+};
+use anyhow::Result;
+use math::vec::{Vec2, Vec3, Vec4};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SceneSourceError {
+    #[error("No scene importer for files with extension {0:?}")]
+    UnsupportedExtension(String),
+}
+
+// A loaded scene file that can be compiled, along with the images its materials reference
+pub trait SceneSource {
+    fn compile(&self) -> Result<CompiledScene>;
+    fn image_data(&self) -> &[ImageData];
+    fn texture_data(&self) -> &[TextureData];
+    // Files read besides the scene file itself
+    fn external_files(&self) -> &[PathBuf];
+}
+
+impl SceneSource for GltfScene {
+    fn compile(&self) -> Result<CompiledScene> {
+        Ok(GltfScene::compile(self)?)
+    }
+
+    fn image_data(&self) -> &[ImageData] {
+        GltfScene::image_data(self)
+    }
+
+    fn texture_data(&self) -> &[TextureData] {
+        GltfScene::texture_data(self)
+    }
+
+    fn external_files(&self) -> &[PathBuf] {
+        GltfScene::external_files(self)
+    }
+}
+
+// Picks the importer from the file extension
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Box<dyn SceneSource>> {
+    let path = path.as_ref();
+    from_slice(path, &std::fs::read(path)?)
+}
+
+// Like `GltfScene::load_cached`, for any supported file type
+pub fn load_cached_scene(path: impl AsRef<Path>) -> Result<(CachedScene, Option<anyhow::Error>)> {
+    cache::load_or_compile(path.as_ref(), |path, source| {
+        let scene = from_slice(path, source)?;
+        let cached_scene = CachedScene {
+            compiled_scene: scene.compile()?,
+            images: scene.image_data().to_vec(),
+            textures: scene.texture_data().to_vec(),
+        };
+        Ok((cached_scene, scene.external_files().to_vec()))
+    })
+}
+
+fn from_slice(path: &Path, slice: &[u8]) -> Result<Box<dyn SceneSource>> {
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    Ok(match extension.as_str() {
+        "gltf" | "glb" => Box::new(GltfScene::from_gltf(slice, Some(base))?),
+        "obj" => Box::new(ObjScene::from_obj(slice, Some(base))?),
+        "ply" => Box::new(PlyScene::from_ply(slice)?),
+        _ => return Err(SceneSourceError::UnsupportedExtension(extension).into()),
+    })
+}
+
+// Vertices of a primitive without skinning, morph targets or extra color and uv sets, as read
+// by the OBJ and PLY importers
+#[derive(Default)]
+pub(super) struct MeshData {
+    pub positions: Vec<Vec4>,
+    pub colors: Vec<Vec4>,
+    pub uvs: Vec<Vec2>,
+    // `None` if the file had no normals, they are generated when the primitive is pushed
+    pub normals: Option<Vec<Vec3>>,
+    pub indices: Vec<u32>,
+    pub topology: Topology,
+}
+
+impl MeshData {
+    pub fn push_primitive(
+        self,
+        material_idx: u32,
+        normal_generation: NormalGeneration,
+        compiled_scene: &mut CompiledScene,
+    ) -> u32 {
+        let num_vertices = self.positions.len();
+        PrimitiveData {
+            positions: self.positions,
+            color_sets: vec![self.colors],
+            uv_sets: vec![self.uvs],
+            normals: self.normals,
+            tangents: None,
+            joints: vec![[0; 4]; num_vertices],
+            weights: vec![Vec4::new(0.0, 0.0, 0.0, 0.0); num_vertices],
+            morph_targets: None,
+            indices: self.indices,
+            topology: self.topology,
+        }
+        .push(material_idx, normal_generation, compiled_scene)
+    }
+}
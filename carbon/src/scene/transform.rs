@@ -0,0 +1,80 @@
+use math::{
+    mat::Mat4,
+    vec::{Vec3, Vec4},
+};
+
+// Quaternions are stored as (x, y, z, w), matching glTF
+pub fn slerp(a: Vec4, b: Vec4, t: f32) -> Vec4 {
+    let mut cos_theta = a.dot(&b);
+    // Take the shortest path around the hypersphere
+    let b = if cos_theta < 0.0 {
+        cos_theta = -cos_theta;
+        b * -1.0
+    } else {
+        b
+    };
+    if cos_theta > 0.9995 {
+        return (a + (b - a) * t).normalized();
+    }
+    let theta = cos_theta.acos();
+    let sin_theta = theta.sin();
+    let a_factor = ((1.0 - t) * theta).sin() / sin_theta;
+    let b_factor = (t * theta).sin() / sin_theta;
+    a * a_factor + b * b_factor
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Vec4,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl From<gltf::scene::Transform> for Transform {
+    fn from(transform: gltf::scene::Transform) -> Self {
+        let (translation, rotation, scale) = transform.decomposed();
+        Self {
+            translation: translation.into(),
+            rotation: rotation.into(),
+            scale: scale.into(),
+        }
+    }
+}
+
+impl Transform {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: slerp(self.rotation, other.rotation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn matrix(&self) -> Mat4 {
+        let (x, y, z, w) = (
+            self.rotation.x(),
+            self.rotation.y(),
+            self.rotation.z(),
+            self.rotation.w(),
+        );
+        let (sx, sy, sz) = (self.scale.x(), self.scale.y(), self.scale.z());
+        let t = self.translation;
+        Mat4::from_data(
+            (1.0 - 2.0 * (y * y + z * z)) * sx, 2.0 * (x * y - z * w) * sy,         2.0 * (x * z + y * w) * sz,         t.x(),
+            2.0 * (x * y + z * w) * sx,         (1.0 - 2.0 * (x * x + z * z)) * sy, 2.0 * (y * z - x * w) * sz,         t.y(),
+            2.0 * (x * z - y * w) * sx,         2.0 * (y * z + x * w) * sy,         (1.0 - 2.0 * (x * x + y * y)) * sz, t.z(),
+            0.0,                                0.0,                                0.0,                                1.0,
+        )
+    }
+}
@@ -4,10 +4,11 @@ use carbon::{
     egui,
     egui_integration::{self, EguiIntegration},
     input::{KeyboardState, MouseState},
-    scene::GltfScene,
+    scene::{self, CachedScene, Filter, SamplerData, Topology, WrapMode},
     vertex::Vertex,
 };
 use easy_ash::{
+    ash::vk,
     math::{
         mat::Mat4,
         vec::{Vec2, Vec4},
@@ -19,6 +20,7 @@ use easy_ash::{
     PushConstant, RenderPass, RenderPassAttachment, Sampler, SamplerFilter, SamplerWrapMode,
     Semaphore, Shader, ShaderStage, Surface, Swapchain,
 };
+use std::collections::HashMap;
 use winit::{
     dpi::LogicalSize,
     event::{Event, VirtualKeyCode},
@@ -43,6 +45,36 @@ struct MaterialPushConstantData {
     pad_3: u32,
 }
 
+// easy-ash samplers take a single filter and wrap mode, so texture samplers are created
+// directly with every setting from the asset
+fn new_texture_sampler(device: &Device, sampler: &SamplerData) -> vk::Sampler {
+    let filter = |filter| match filter {
+        Filter::Nearest => vk::Filter::NEAREST,
+        Filter::Linear => vk::Filter::LINEAR,
+    };
+    let address_mode = |wrap_mode| match wrap_mode {
+        WrapMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrapMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrapMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+    // Without a mipmap mode only the base level is sampled
+    let (mipmap_mode, max_lod) = match sampler.mipmap_mode {
+        Some(Filter::Nearest) => (vk::SamplerMipmapMode::NEAREST, vk::LOD_CLAMP_NONE),
+        Some(Filter::Linear) => (vk::SamplerMipmapMode::LINEAR, vk::LOD_CLAMP_NONE),
+        None => (vk::SamplerMipmapMode::NEAREST, 0.0),
+    };
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(filter(sampler.mag_filter))
+        .min_filter(filter(sampler.min_filter))
+        .mipmap_mode(mipmap_mode)
+        .address_mode_u(address_mode(sampler.wrap_s))
+        .address_mode_v(address_mode(sampler.wrap_t))
+        .address_mode_w(address_mode(sampler.wrap_t))
+        .min_lod(0.0)
+        .max_lod(max_lod);
+    unsafe { device.device.create_sampler(&create_info, None) }.expect("Could not create sampler")
+}
+
 fn main() {
     let app_title = "Generative Art";
     let window_width = 1200;
@@ -133,14 +165,27 @@ fn main() {
         .expect("Could not create sampler");
 
     // Scene setup start
-    let gltf_scene = GltfScene::new("glTF-Sample-Models/2.0/BoxTextured/glTF/BoxTextured.gltf")
-        .expect("Coult not load gltf scene");
-    let mut compiled_scene = gltf_scene.compile().expect("Could not compile Gltf Scene");
+    // The importer is picked from the extension, so obj and ply files work as well
+    let scene_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "glTF-Sample-Models/2.0/BoxTextured/glTF/BoxTextured.gltf".to_owned());
+    let (
+        CachedScene {
+            mut compiled_scene,
+            images,
+            textures,
+        },
+        cache_error,
+    ) = scene::load_cached_scene(&scene_path).expect("Could not load scene");
+    if let Some(err) = cache_error {
+        eprintln!("{:#}", err);
+    }
 
-    let images = gltf_scene.image_data();
     let images_data = images
         .iter()
         .map(|image_data| {
+            // TODO: Upload 16-bit and float images without losing precision
+            let image_data = image_data.to_rgba8();
             Image::from_data_and_dims(
                 &device,
                 image_data.width,
@@ -161,15 +206,37 @@ fn main() {
     let index_buffer = Buffer::from_data(&device, BufferType::Index, &compiled_scene.indices)
         .expect("Could not create index buffer");
 
+    // Morph targets and skins are applied once, in the scene's initial pose. Skinned vertices
+    // end up in world space, matching the identity `transform_matrix` of their draws.
+    let (mut positions, mut normals) = compiled_scene.morphed_vertices(&[]);
+    compiled_scene.apply_skinning(
+        compiled_scene.graph.world_transforms(),
+        &mut positions,
+        &mut normals,
+    );
     let vertex_buffer_data = {
         let mut ret: Vec<Vertex> = Vec::with_capacity(compiled_scene.positions.len());
-        for idx in 0..compiled_scene.positions.len() {
-            ret.push(Vertex {
-                pos: compiled_scene.positions[idx],
-                color: compiled_scene.colors[idx],
-                uv: compiled_scene.uvs[idx],
-                pad: Default::default(),
-            });
+        for primitive in &compiled_scene.primitives {
+            // Vertices only have room for one uv set, use the one the base color is sampled with
+            // and bake its texture transform in
+            let base_color_texture = compiled_scene.materials[primitive.material_idx as usize]
+                .metallic_roughness
+                .base_color_texture;
+            let uv_set = base_color_texture.map_or(0, |info| info.tex_coord as usize);
+            let uv_transform = base_color_texture.and_then(|info| info.transform);
+            let uvs = compiled_scene
+                .uv_sets
+                .get(uv_set)
+                .unwrap_or(&compiled_scene.uv_sets[0]);
+            let start = primitive.start_vertex as usize;
+            for idx in start..start + primitive.num_vertices as usize {
+                ret.push(Vertex {
+                    pos: positions[idx],
+                    color: compiled_scene.color_sets[0][idx],
+                    uv: uv_transform.map_or(uvs[idx], |transform| transform.transform_uv(uvs[idx])),
+                    pad: Default::default(),
+                });
+            }
         }
         ret
     };
@@ -189,10 +256,24 @@ fn main() {
 
     let descriptor_pool = DescriptorPool::new(&device).expect("Could not create descriptor pool");
     let texture_array_count = 40;
+    // Descriptor slots are indexed by gltf texture, with the white texture right after them
+    let white_texture_index = textures.len();
+    // One sampler per distinct sampler setting, shared by every texture that uses it
+    let mut texture_samplers = HashMap::new();
+    for texture in &textures {
+        texture_samplers
+            .entry(texture.sampler)
+            .or_insert_with_key(|sampler| new_texture_sampler(&device, sampler));
+    }
     let infos = {
-        let mut infos = images_data
+        let mut infos = textures
             .iter()
-            .map(|data| new_descriptor_image_info(&data.0, &sampler))
+            .map(|texture| {
+                let mut info =
+                    new_descriptor_image_info(&images_data[texture.image_index].0, &sampler);
+                info.sampler = texture_samplers[&texture.sampler];
+                info
+            })
             .collect::<Vec<_>>();
         infos.resize_with(texture_array_count as usize, || {
             new_descriptor_image_info(&images_data.last().unwrap().0, &sampler)
@@ -296,6 +377,9 @@ fn main() {
                         descriptor_pool.clean(&device);
                         swapchain.clean(&device);
                         sampler.clean(&device);
+                        for texture_sampler in texture_samplers.values() {
+                            unsafe { device.device.destroy_sampler(*texture_sampler, None) };
+                        }
                         for (image, staging_buffer) in &images_data {
                             image.clean(&device);
                             // TODO: This should be freed much sooner, handle later after we figure out syncronization
@@ -360,13 +444,22 @@ fn main() {
                             &global_descriptor_set,
                             0,
                         );
-                        for mesh_draw in &compiled_scene.mesh_draws {
+                        let frustum =
+                            camera.frustum(window_size.width as f32, window_size.height as f32);
+                        // TODO: Draw points and lines, the pipeline only rasterizes triangle lists
+                        for mesh_draw in compiled_scene
+                            .visible_draws(&frustum)
+                            .filter(|mesh_draw| mesh_draw.topology == Topology::Triangles)
+                        {
+                            let primitive =
+                                &compiled_scene.primitives[mesh_draw.primitive_idx as usize];
                             {
+                                let transform_matrix = compiled_scene.transform_matrix(mesh_draw);
                                 device.push_constant(
                                     context,
                                     &graphics_pipeline,
                                     &camera_push_constant,
-                                    easy_ash::as_u8_slice(&mesh_draw.transform_matrix),
+                                    easy_ash::as_u8_slice(&transform_matrix),
                                 );
 
                                 // todo: Better abstraction for setting material data later
@@ -374,8 +467,9 @@ fn main() {
                                     &compiled_scene.materials[mesh_draw.material_idx as usize];
                                 let texture_index = material
                                     .metallic_roughness
-                                    .texture_index
-                                    .unwrap_or(images_data.len() - 1)
+                                    .base_color_texture
+                                    .map(|info| info.texture_index)
+                                    .unwrap_or(white_texture_index)
                                     as u32;
 
                                 let material_data = MaterialPushConstantData {
@@ -394,8 +488,8 @@ fn main() {
 
                             device.draw_indexed(
                                 context,
-                                mesh_draw.num_indices,
-                                mesh_draw.start_idx,
+                                primitive.num_indices,
+                                primitive.start_idx,
                                 0,
                             );
                         }